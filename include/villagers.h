@@ -0,0 +1,106 @@
+#ifndef VILLAGERS_H
+#define VILLAGERS_H
+
+/* 由cbindgen根据src/capi.rs生成, 请勿手动修改 */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define VILLAGERS_OK 0
+
+#define VILLAGERS_ERROR -1
+
+#define VILLAGERS_CANCELLED 1
+
+#define VILLAGERS_SUBTITLE_MAJOR 0
+
+#define VILLAGERS_SUBTITLE_MINOR 1
+
+/**
+ * 压制任务, 由villagers_job_new创建, villagers_job_free释放
+ *
+ * villagers_job_cancel可能与其他函数同时调用, 各函数只通过共享引用访问任务
+ */
+typedef struct VillagersJob VillagersJob;
+
+/**
+ * 进度回调的参数
+ */
+typedef struct VillagersProgress {
+  int64_t frames_done;
+  int64_t total_frames;
+  double fps;
+  /**
+   * 已用秒数
+   */
+  double elapsed;
+  /**
+   * 预计剩余秒数, 未知时为-1
+   */
+  double eta;
+  /**
+   * 压制结束时为1
+   */
+  int finished;
+} VillagersProgress;
+
+typedef void (*VillagersProgressCallback)(const struct VillagersProgress *progress, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * 创建任务, ffmpeg初始化失败时返回NULL
+ */
+struct VillagersJob *villagers_job_new(void);
+
+void villagers_job_free(struct VillagersJob *job);
+
+/**
+ * 设置选项, key与命令行长参数同名, 如"input", "bottom-offset"; 开关类选项的value传NULL
+ *
+ * 可多次设置的选项 (如input) 每次调用追加一个值
+ */
+int villagers_job_set_option(struct VillagersJob *job, const char *key, const char *value);
+
+/**
+ * 从内存中的PNG图片添加字幕, 帧号范围与字幕文件名中的相同, 须满足 1 <= begin_frame <= end_frame
+ *
+ * 添加过字幕时不再读取subtitle-files指定的文件夹; 图片数据在函数返回后即可释放
+ */
+int villagers_job_add_subtitle(struct VillagersJob *job,
+                               int kind,
+                               uint64_t id,
+                               uint64_t begin_frame,
+                               uint64_t end_frame,
+                               const uint8_t *data,
+                               size_t len);
+
+/**
+ * 运行压制, 阻塞至完成; callback可为NULL, 在压制线程中调用
+ *
+ * 成功返回VILLAGERS_OK, 被取消时返回VILLAGERS_CANCELLED, 此时输出为截断但可播放的文件
+ */
+int villagers_job_run(struct VillagersJob *job,
+                      VillagersProgressCallback callback,
+                      void *user_data);
+
+/**
+ * 请求停止正在运行的压制, 可在其他线程中调用; 取消后任务不能再次运行
+ */
+void villagers_job_cancel(const struct VillagersJob *job);
+
+/**
+ * 最近一次出错的信息, 没有错误时返回NULL; 在下一次调用该任务的函数前有效
+ */
+const char *villagers_job_last_error(const struct VillagersJob *job);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* VILLAGERS_H */
@@ -0,0 +1,5 @@
+#!/bin/sh
+# 重新生成C接口的头文件, 需先安装cbindgen: cargo install cbindgen
+set -e
+cd "$(dirname "$0")/.."
+cbindgen --config cbindgen.toml --crate villagers-embedding-tool --output include/villagers.h
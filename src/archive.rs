@@ -0,0 +1,233 @@
+use std::{
+    fs::File,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use flate2::read::GzDecoder;
+use log::{debug, info};
+use zip::ZipArchive;
+
+use crate::{
+    image::decode_png,
+    subtitle::{Subtitle, SubtitleSource},
+};
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn format_of(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if name.ends_with(".zip") {
+        return Some(Format::Zip);
+    }
+    if name.ends_with(".tar") {
+        return Some(Format::Tar);
+    }
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        return Some(Format::TarGz);
+    }
+    return None;
+}
+
+/// 是否为可读取字幕的压缩包 (zip, tar, tar.gz)
+pub fn is_archive(path: &Path) -> bool {
+    path.is_file() && format_of(path).is_some()
+}
+
+/// 字幕图片压缩包, 直接在内存中解码符合文件名规则的条目, 不解压到磁盘
+///
+/// 只按条目的文件名匹配, 压缩包内的文件夹层级不影响读取
+pub struct ArchiveSource {
+    path: PathBuf,
+}
+
+impl ArchiveSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+// Windows下创建的压缩包中, 条目名可能以\分隔文件夹
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+/// 压缩工具附带的元数据条目, 如macOS的__MACOSX/._*.png, 不计入被忽略的文件
+fn is_metadata_entry(name: &str) -> bool {
+    name.split(is_separator)
+        .any(|part| part == "__MACOSX" || (part.starts_with('.') && part != "." && part != ".."))
+}
+
+#[derive(Default)]
+struct Loaded {
+    subtitles: Vec<Subtitle>,
+    ignored: usize,
+}
+
+impl Loaded {
+    fn add(&mut self, name: &str, entry: &mut impl Read) -> anyhow::Result<()> {
+        if is_metadata_entry(name) {
+            debug!("Skipping metadata entry: {}", name);
+            return Ok(());
+        }
+        let filename = name.rsplit(is_separator).next().unwrap_or(name);
+        debug!("Reading: {}", name);
+        let subtitle = Subtitle::from_file_name(filename, || {
+            let mut data = vec![];
+            entry.read_to_end(&mut data)?;
+            decode_png(&data)
+        })?;
+        match subtitle {
+            Some(subtitle) => self.subtitles.push(subtitle),
+            None => {
+                info!("Ignoring file: {}", name);
+                self.ignored += 1;
+            }
+        }
+        return Ok(());
+    }
+    fn read_zip(&mut self, reader: impl Read + Seek) -> anyhow::Result<()> {
+        let mut archive = ZipArchive::new(reader).map_err(|e| anyhow!("Invalid zip: {}", e))?;
+        for index in 0..archive.len() {
+            let mut entry = archive
+                .by_index(index)
+                .map_err(|e| anyhow!("Failed to read zip entry: {}", e))?;
+            if entry.is_dir() {
+                continue;
+            }
+            let name = entry.name().to_string();
+            self.add(&name, &mut entry)?;
+        }
+        return Ok(());
+    }
+    fn read_tar(&mut self, reader: impl Read) -> anyhow::Result<()> {
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive
+            .entries()
+            .map_err(|e| anyhow!("Invalid tar: {}", e))?
+        {
+            let mut entry = entry.map_err(|e| anyhow!("Failed to read tar entry: {}", e))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry
+                .path()
+                .map_err(|e| anyhow!("Invalid tar entry name: {}", e))?
+                .to_string_lossy()
+                .to_string();
+            self.add(&name, &mut entry)?;
+        }
+        return Ok(());
+    }
+}
+
+impl SubtitleSource for ArchiveSource {
+    fn load(&mut self) -> anyhow::Result<(Vec<Subtitle>, usize)> {
+        let format =
+            format_of(&self.path).ok_or(anyhow!("Unsupported archive: {}", self.path.display()))?;
+        let file = File::open(&self.path)
+            .map_err(|e| anyhow!("Failed to open {}: {}", self.path.display(), e))?;
+        info!(
+            "Reading subtitles from {:?} archive {}",
+            format,
+            self.path.display()
+        );
+        let mut loaded = Loaded::default();
+        match format {
+            Format::Zip => loaded.read_zip(file),
+            Format::Tar => loaded.read_tar(file),
+            Format::TarGz => loaded.read_tar(GzDecoder::new(file)),
+        }
+        .map_err(|e| anyhow!("{}: {}", self.path.display(), e))?;
+        return Ok((loaded.subtitles, loaded.ignored));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use ffmpeg_next::{format::Pixel, frame::Video};
+    use flate2::{write::GzEncoder, Compression};
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+    use crate::image::encode_png;
+
+    #[test]
+    fn skips_metadata_entries() {
+        assert!(is_metadata_entry("__MACOSX/._major-subtitle-1-1-10.png"));
+        assert!(is_metadata_entry(
+            "__MACOSX/subs/._major-subtitle-1-1-10.png"
+        ));
+        assert!(is_metadata_entry("subs/._major-subtitle-1-1-10.png"));
+        assert!(is_metadata_entry(".DS_Store"));
+        assert!(!is_metadata_entry("major-subtitle-1-1-10.png"));
+        assert!(!is_metadata_entry("subs/minor-subtitle-2-5-9.png"));
+        assert!(!is_metadata_entry("./subs/minor-subtitle-2-5-9.png"));
+        assert!(is_metadata_entry("subs\\._major-subtitle-1-1-10.png"));
+        assert!(!is_metadata_entry("subs\\minor-subtitle-2-5-9.png"));
+    }
+
+    // (条目名, 是否写入有效的PNG)
+    const ENTRIES: [(&str, bool); 5] = [
+        ("subs/major-subtitle-1-1-10.png", true),
+        ("subs\\minor-subtitle-2-5-9.png", true),
+        ("__MACOSX/subs/._major-subtitle-1-1-10.png", false),
+        ("subs/readme.txt", false),
+        ("notes.md", false),
+    ];
+
+    fn png() -> Vec<u8> {
+        let frame = Video::new(Pixel::RGB24, 4, 2);
+        return encode_png(&frame).unwrap();
+    }
+
+    fn assert_loaded(loaded: Loaded) {
+        assert_eq!(loaded.subtitles.len(), 2);
+        assert_eq!(loaded.ignored, 2);
+        assert!(loaded
+            .subtitles
+            .iter()
+            .any(|subtitle| subtitle.begin_flap == 5 && subtitle.end_flap == 9));
+    }
+
+    #[test]
+    fn reads_zip_in_memory() {
+        let png = png();
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, valid) in ENTRIES {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer
+                .write_all(if valid { &png } else { b"not a subtitle" })
+                .unwrap();
+        }
+        let data = writer.finish().unwrap().into_inner();
+        let mut loaded = Loaded::default();
+        loaded.read_zip(Cursor::new(data)).unwrap();
+        assert_loaded(loaded);
+    }
+
+    #[test]
+    fn reads_tar_gz_in_memory() {
+        let png = png();
+        let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for (name, valid) in ENTRIES {
+            let data: &[u8] = if valid { &png } else { b"not a subtitle" };
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, data).unwrap();
+        }
+        let data = builder.into_inner().unwrap().finish().unwrap();
+        let mut loaded = Loaded::default();
+        loaded.read_tar(GzDecoder::new(data.as_slice())).unwrap();
+        assert_loaded(loaded);
+    }
+}
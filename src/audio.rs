@@ -0,0 +1,275 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use ffmpeg_next::{
+    codec::{self, Context},
+    decoder, encoder, filter,
+    format::{self, context::Output},
+    frame, ChannelLayout, Error, Packet, Rational, Stream,
+};
+use ffmpeg_sys_next::{avcodec_alloc_context3, avcodec_parameters_to_context};
+use log::info;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioCodec {
+    Copy,
+    Aac,
+    Opus,
+    Flac,
+}
+
+impl FromStr for AudioCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "copy" => Ok(Self::Copy),
+            "aac" => Ok(Self::Aac),
+            "opus" => Ok(Self::Opus),
+            "flac" => Ok(Self::Flac),
+            _ => Err(format!("Unsupported audio codec: {}", s)),
+        }
+    }
+}
+
+impl AudioCodec {
+    fn encoder_names(&self) -> &'static [&'static str] {
+        match self {
+            Self::Copy => &[],
+            Self::Aac => &["aac"],
+            Self::Opus => &["libopus", "opus"],
+            Self::Flac => &["flac"],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioSettings {
+    pub codec: AudioCodec,
+    pub bitrate: Option<usize>,
+    // EBU R128目标响度 (LUFS)
+    pub loudness: Option<f64>,
+}
+
+/// 单条音频流的 解码 -> 滤镜(响度标准化, 重采样) -> 编码
+pub struct AudioTranscoder {
+    encoder_time_base: Rational,
+    output_index: usize,
+    decoder: decoder::Audio,
+    encoder: encoder::Audio,
+    filter: filter::Graph,
+}
+
+impl AudioTranscoder {
+    pub fn new(
+        input_stream: &Stream,
+        output_ctx: &mut Output,
+        settings: &AudioSettings,
+    ) -> anyhow::Result<Self> {
+        let encoder_codec = settings
+            .codec
+            .encoder_names()
+            .iter()
+            .find_map(|name| encoder::find_by_name(name))
+            .ok_or(anyhow!("Missing encoder for {:?}", settings.codec))?;
+        let audio_codec = encoder_codec.audio()?;
+        let context_decoder = {
+            let parameters = input_stream.parameters();
+            let mut context = codec::context::Context::new();
+
+            unsafe {
+                match avcodec_parameters_to_context(context.as_mut_ptr(), parameters.as_ptr()) {
+                    e if e < 0 => Err(Error::from(e)),
+                    _ => Ok(context),
+                }
+            }?
+        };
+        let decoder = context_decoder.decoder().audio()?;
+        let input_layout = if decoder.channel_layout().is_empty() {
+            ChannelLayout::default(decoder.channels() as i32)
+        } else {
+            decoder.channel_layout()
+        };
+        let channel_layout = audio_codec
+            .channel_layouts()
+            .map(|layouts| layouts.best(input_layout.channels()))
+            .unwrap_or(input_layout);
+        let sample_format = audio_codec
+            .formats()
+            .and_then(|mut formats| formats.next())
+            .ok_or(anyhow!("Unknown sample formats for {:?}", settings.codec))?;
+        let rate = match audio_codec.rates() {
+            Some(rates) => {
+                let rates = rates.collect::<Vec<i32>>();
+                if rates.contains(&(decoder.rate() as i32)) {
+                    decoder.rate() as i32
+                } else {
+                    *rates.iter().max().unwrap_or(&48000)
+                }
+            }
+            None => decoder.rate() as i32,
+        };
+        let global_header = output_ctx
+            .format()
+            .flags()
+            .contains(format::Flags::GLOBAL_HEADER);
+
+        let mut output_stream = output_ctx
+            .add_stream(encoder_codec)
+            .map_err(|e| anyhow!("Failed to add stream: {}", e))?;
+        let output_index = output_stream.index();
+        let context_encoder =
+            unsafe { Context::wrap(avcodec_alloc_context3(encoder_codec.as_ptr()), None) };
+        let mut encoder = context_encoder.encoder().audio()?;
+        if global_header {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        encoder.set_rate(rate);
+        encoder.set_channel_layout(channel_layout);
+        encoder.set_channels(channel_layout.channels());
+        encoder.set_format(sample_format);
+        if settings.codec != AudioCodec::Flac {
+            encoder.set_bit_rate(settings.bitrate.unwrap_or(192000));
+        }
+        encoder.set_time_base((1, rate));
+        output_stream.set_time_base((1, rate));
+        let encoder = encoder.open_as(encoder_codec)?;
+        output_stream.set_parameters(&encoder);
+
+        let filter = Self::build_filter(
+            input_stream.time_base(),
+            &decoder,
+            input_layout,
+            &encoder,
+            settings.loudness,
+        )?;
+        info!(
+            "Audio stream {} -> {}: {} -> {}, {} Hz, {} channels, loudness target: {:?}",
+            input_stream.index(),
+            output_index,
+            decoder.id().name(),
+            encoder_codec.name(),
+            rate,
+            channel_layout.channels(),
+            settings.loudness
+        );
+        return Ok(Self {
+            encoder_time_base: Rational::new(1, rate),
+            output_index,
+            decoder,
+            encoder,
+            filter,
+        });
+    }
+    fn build_filter(
+        time_base: Rational,
+        decoder: &decoder::Audio,
+        input_layout: ChannelLayout,
+        encoder: &encoder::Audio,
+        loudness: Option<f64>,
+    ) -> anyhow::Result<filter::Graph> {
+        let mut graph = filter::Graph::new();
+        let args = format!(
+            "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            time_base,
+            decoder.rate(),
+            decoder.format().name(),
+            input_layout.bits()
+        );
+        graph.add(&filter::find("abuffer").unwrap(), "in", &args)?;
+        graph.add(&filter::find("abuffersink").unwrap(), "out", "")?;
+        {
+            let mut out = graph.get("out").unwrap();
+            out.set_sample_format(encoder.format());
+            out.set_channel_layout(encoder.channel_layout());
+            out.set_sample_rate(encoder.rate());
+        }
+        // 输出帧的时间基须与编码器一致 (1/采样率); 不重采样时滤镜保留输入流的时间基, 如MKV的1/1000
+        let time_base = format!("asettb=1/{}", encoder.rate());
+        let spec = match loudness {
+            // loudnorm内部会上采样到192kHz, 需重采样回编码器的采样率
+            Some(target) => format!(
+                "loudnorm=I={}:TP=-1.5:LRA=11,aresample={},{}",
+                target,
+                encoder.rate(),
+                time_base
+            ),
+            None => time_base,
+        };
+        graph.output("in", 0)?.input("out", 0)?.parse(&spec)?;
+        graph.validate()?;
+        if let Some(codec) = encoder.codec() {
+            if !codec
+                .capabilities()
+                .contains(codec::Capabilities::VARIABLE_FRAME_SIZE)
+            {
+                graph
+                    .get("out")
+                    .unwrap()
+                    .sink()
+                    .set_frame_size(encoder.frame_size());
+            }
+        }
+        return Ok(graph);
+    }
+    pub fn send_packet(&mut self, packet: &Packet, output_ctx: &mut Output) -> anyhow::Result<()> {
+        self.decoder
+            .send_packet(packet)
+            .map_err(|e| anyhow!("Failed to send packet to audio decoder: {}", e))?;
+        return self.receive_decoded(output_ctx);
+    }
+    fn receive_decoded(&mut self, output_ctx: &mut Output) -> anyhow::Result<()> {
+        let mut decoded = frame::Audio::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            self.filter
+                .get("in")
+                .unwrap()
+                .source()
+                .add(&decoded)
+                .map_err(|e| anyhow!("Failed to send frame to audio filter: {}", e))?;
+            self.receive_filtered(output_ctx)?;
+        }
+        return Ok(());
+    }
+    fn receive_filtered(&mut self, output_ctx: &mut Output) -> anyhow::Result<()> {
+        let mut filtered = frame::Audio::empty();
+        while self
+            .filter
+            .get("out")
+            .unwrap()
+            .sink()
+            .frame(&mut filtered)
+            .is_ok()
+        {
+            self.encoder
+                .send_frame(&filtered)
+                .map_err(|e| anyhow!("Failed to send frame to audio encoder: {}", e))?;
+            self.receive_encoded(output_ctx)?;
+        }
+        return Ok(());
+    }
+    fn receive_encoded(&mut self, output_ctx: &mut Output) -> anyhow::Result<()> {
+        let mut encoded = Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            let output_time_base = output_ctx.stream(self.output_index).unwrap().time_base();
+            encoded.set_stream(self.output_index);
+            encoded.rescale_ts(self.encoder_time_base, output_time_base);
+            encoded
+                .write_interleaved(output_ctx)
+                .map_err(|e| anyhow!("Failed to write audio packet: {}", e))?;
+        }
+        return Ok(());
+    }
+    /// 冲刷解码器, 滤镜与编码器中剩余的数据
+    pub fn finish(&mut self, output_ctx: &mut Output) -> anyhow::Result<()> {
+        self.decoder.send_eof()?;
+        self.receive_decoded(output_ctx)?;
+        self.filter.get("in").unwrap().source().flush()?;
+        self.receive_filtered(output_ctx)?;
+        self.encoder.send_eof()?;
+        self.receive_encoded(output_ctx)?;
+        return Ok(());
+    }
+}
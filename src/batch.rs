@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use log::{error, info};
+use rayon::prelude::*;
+use serde::Serialize;
+use toml::Value;
+
+use crate::{
+    cmdline::BatchArg,
+    jobs::{run_job, Job, JobResult, JobStatus},
+};
+
+/// 任务名用作日志文件名, 不能包含路径分隔符与 `..`
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains("..")
+        && !name
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c == ':' || c.is_control())
+}
+
+/// 读取任务文件
+///
+/// `[defaults]` 中的参数对所有任务生效, 每个 `[[job]]` 中的参数覆盖默认值;
+/// 可选的 `name` 用于日志文件名, 缺省时取输出文件名
+fn load_jobs(path: &Path) -> anyhow::Result<Vec<Job>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read job file {}: {}", path.display(), e))?;
+    let root = content
+        .parse::<Value>()
+        .map_err(|e| anyhow!("Invalid job file {}: {}", path.display(), e))?;
+    let defaults = match root.get("defaults") {
+        Some(Value::Table(table)) => table.clone(),
+        Some(_) => return Err(anyhow!("[defaults] in {} must be a table", path.display())),
+        None => Default::default(),
+    };
+    let entries = root
+        .get("job")
+        .and_then(|jobs| jobs.as_array())
+        .ok_or(anyhow!("No [[job]] found in {}", path.display()))?;
+    // 相对路径均相对于任务文件所在目录
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let mut jobs = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        let mut table = defaults.clone();
+        for (key, value) in entry
+            .as_table()
+            .ok_or(anyhow!(
+                "Job {} in {} must be a table",
+                i + 1,
+                path.display()
+            ))?
+            .iter()
+        {
+            table.insert(key.clone(), value.clone());
+        }
+        let name = match table.remove("name") {
+            Some(Value::String(name)) if is_valid_name(&name) => name,
+            Some(Value::String(name)) => {
+                return Err(anyhow!(
+                    "Invalid name of job {}: {:?}, must not contain path separators or ..",
+                    i + 1,
+                    name
+                ))
+            }
+            Some(value) => return Err(anyhow!("Invalid name of job {}: {}", i + 1, value)),
+            None => table
+                .get("output")
+                .and_then(|output| output.as_str())
+                .and_then(|output| Path::new(output).file_stem())
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or(format!("job-{}", i + 1)),
+        };
+        jobs.push(Job::from_table(
+            format!("{:03}-{}", i + 1, name),
+            &table,
+            &dir,
+        )?);
+    }
+    return Ok(jobs);
+}
+
+#[derive(Serialize)]
+struct BatchReport {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    jobs: Vec<JobResult>,
+}
+
+/// 按任务文件依次压制, 失败的任务不影响后续任务, 结束后写入状态报告
+pub fn run(batch_arg: &BatchArg) -> anyhow::Result<()> {
+    let jobs = load_jobs(Path::new(&batch_arg.job_file))?;
+    std::fs::create_dir_all(&batch_arg.log_dir)
+        .map_err(|e| anyhow!("Failed to create {}: {}", batch_arg.log_dir, e))?;
+    let log_dir = std::fs::canonicalize(&batch_arg.log_dir)
+        .map_err(|e| anyhow!("Failed to resolve {}: {}", batch_arg.log_dir, e))?;
+    info!(
+        "{} jobs loaded, running {} at a time",
+        jobs.len(),
+        batch_arg.jobs
+    );
+
+    // 各任务在子进程中运行, 此处的线程仅用于等待
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(batch_arg.jobs.max(1))
+        .build()
+        .map_err(|e| anyhow!("Failed to create thread pool: {}", e))?;
+    let results = pool.install(|| {
+        jobs.par_iter()
+            .map(|job| {
+                info!("Job {} started", job.name);
+                let result = run_job(job, &log_dir.join(format!("{}.log", job.name)));
+                match result.status {
+                    JobStatus::Ok => {
+                        info!("Job {} finished in {:.1} secs", job.name, result.elapsed)
+                    }
+                    JobStatus::Failed => error!(
+                        "Job {} failed: {}, see {}",
+                        job.name,
+                        result.error.as_deref().unwrap_or("unknown error"),
+                        result.log
+                    ),
+                }
+                result
+            })
+            .collect::<Vec<JobResult>>()
+    });
+
+    let failed = results
+        .iter()
+        .filter(|result| result.status == JobStatus::Failed)
+        .count();
+    let report = BatchReport {
+        total: results.len(),
+        succeeded: results.len() - failed,
+        failed,
+        jobs: results,
+    };
+    let file = std::fs::File::create(&batch_arg.report)
+        .map_err(|e| anyhow!("Failed to create report {}: {}", batch_arg.report, e))?;
+    serde_json::to_writer_pretty(file, &report)
+        .map_err(|e| anyhow!("Failed to write report {}: {}", batch_arg.report, e))?;
+    info!(
+        "Batch finished: {} succeeded, {} failed, report saved to {}",
+        report.succeeded, report.failed, batch_arg.report
+    );
+    if failed > 0 {
+        return Err(anyhow!("{} of {} jobs failed", failed, report.total));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_path_like_names() {
+        assert!(is_valid_name("episode-01"));
+        assert!(is_valid_name("第1话.final"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../escape"));
+        assert!(!is_valid_name(".."));
+        assert!(!is_valid_name("sub/dir"));
+        assert!(!is_valid_name("/etc/passwd"));
+        assert!(!is_valid_name("C:\\logs"));
+    }
+}
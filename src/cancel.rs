@@ -0,0 +1,43 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    CANCELLED.store(true, Ordering::SeqCst);
+    // 再次收到信号时直接退出
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+/// 收到SIGINT/SIGTERM时只记录取消请求, 由压制流程写完当前块后收尾
+pub fn install() {
+    unsafe {
+        libc::signal(libc::SIGINT, handle_signal as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handle_signal as libc::sighandler_t);
+    }
+}
+
+pub fn requested() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// 取消令牌, 可在其他线程中请求停止压制
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+    /// 已通过令牌请求取消, 或进程收到了SIGINT/SIGTERM
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst) || requested()
+    }
+}
@@ -0,0 +1,269 @@
+//! C接口, 启用`capi`特性时编译; 头文件为`include/villagers.h`, 修改接口后运行`scripts/gen-header.sh`重新生成
+//!
+//! 除villagers_job_cancel外, 同一任务的函数不能在多个线程中同时调用
+//!
+//! 构建动态库: `cargo rustc --release --lib --features capi --crate-type cdylib`,
+//! 静态库将cdylib换为staticlib
+
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_void},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    cancel::CancelToken,
+    embedding::EmbeddingBuilder,
+    image::decode_png,
+    progress::ProgressUpdate,
+    subtitle::{Subtitle, SubtitleType},
+};
+
+pub const VILLAGERS_OK: c_int = 0;
+pub const VILLAGERS_ERROR: c_int = -1;
+pub const VILLAGERS_CANCELLED: c_int = 1;
+
+pub const VILLAGERS_SUBTITLE_MAJOR: c_int = 0;
+pub const VILLAGERS_SUBTITLE_MINOR: c_int = 1;
+
+// 任务的参数, 运行前设置
+#[derive(Default)]
+struct Options {
+    args: Vec<String>,
+    subtitles: Vec<Subtitle>,
+}
+
+/// 压制任务, 由villagers_job_new创建, villagers_job_free释放
+///
+/// villagers_job_cancel可能与其他函数同时调用, 各函数只通过共享引用访问任务
+pub struct VillagersJob {
+    options: Mutex<Options>,
+    cancel: CancelToken,
+    error: Mutex<Option<CString>>,
+}
+
+impl VillagersJob {
+    fn fail(&self, e: impl std::fmt::Display) -> c_int {
+        *self.error.lock().unwrap() = CString::new(e.to_string().replace('\0', " ")).ok();
+        return VILLAGERS_ERROR;
+    }
+}
+
+/// 进度回调的参数
+#[repr(C)]
+pub struct VillagersProgress {
+    pub frames_done: i64,
+    pub total_frames: i64,
+    pub fps: f64,
+    /// 已用秒数
+    pub elapsed: f64,
+    /// 预计剩余秒数, 未知时为-1
+    pub eta: f64,
+    /// 压制结束时为1
+    pub finished: c_int,
+}
+
+pub type VillagersProgressCallback =
+    Option<unsafe extern "C" fn(progress: *const VillagersProgress, user_data: *mut c_void)>;
+
+// 回调在压制线程中调用, 由调用方保证user_data可跨线程使用
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+impl UserData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> anyhow::Result<&'a str> {
+    if s.is_null() {
+        return Err(anyhow!("Unexpected null string"));
+    }
+    return CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| anyhow!("Invalid UTF-8 string: {}", e));
+}
+
+/// 创建任务, ffmpeg初始化失败时返回NULL
+#[no_mangle]
+pub extern "C" fn villagers_job_new() -> *mut VillagersJob {
+    if ffmpeg_next::init().is_err() {
+        return ptr::null_mut();
+    }
+    return Box::into_raw(Box::new(VillagersJob {
+        options: Mutex::new(Options::default()),
+        cancel: CancelToken::new(),
+        error: Mutex::new(None),
+    }));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn villagers_job_free(job: *mut VillagersJob) {
+    if !job.is_null() {
+        drop(Box::from_raw(job));
+    }
+}
+
+/// 设置选项, key与命令行长参数同名, 如"input", "bottom-offset"; 开关类选项的value传NULL
+///
+/// 可多次设置的选项 (如input) 每次调用追加一个值
+#[no_mangle]
+pub unsafe extern "C" fn villagers_job_set_option(
+    job: *mut VillagersJob,
+    key: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    let job = match job.as_ref() {
+        Some(job) => job,
+        None => return VILLAGERS_ERROR,
+    };
+    let arg = match (to_str(key), value.is_null()) {
+        (Ok(key), true) => format!("--{}", key),
+        (Ok(key), false) => match to_str(value) {
+            Ok(value) => format!("--{}={}", key, value),
+            Err(e) => return job.fail(e),
+        },
+        (Err(e), _) => return job.fail(e),
+    };
+    let mut options = job.options.lock().unwrap();
+    let mut args = options.args.clone();
+    args.push(arg);
+    // 立即检查, 使错误对应到出错的选项
+    if let Err(e) = EmbeddingBuilder::from_args(&args) {
+        return job.fail(e);
+    }
+    options.args = args;
+    return VILLAGERS_OK;
+}
+
+/// 从内存中的PNG图片添加字幕, 帧号范围与字幕文件名中的相同, 须满足 1 <= begin_frame <= end_frame
+///
+/// 添加过字幕时不再读取subtitle-files指定的文件夹; 图片数据在函数返回后即可释放
+#[no_mangle]
+pub unsafe extern "C" fn villagers_job_add_subtitle(
+    job: *mut VillagersJob,
+    kind: c_int,
+    id: u64,
+    begin_frame: u64,
+    end_frame: u64,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    let job = match job.as_ref() {
+        Some(job) => job,
+        None => return VILLAGERS_ERROR,
+    };
+    let subtitle_type = match kind {
+        VILLAGERS_SUBTITLE_MAJOR => SubtitleType::Major,
+        VILLAGERS_SUBTITLE_MINOR => SubtitleType::Minor,
+        _ => return job.fail(anyhow!("Invalid subtitle kind: {}", kind)),
+    };
+    if begin_frame == 0 || begin_frame > end_frame {
+        return job.fail(anyhow!(
+            "Invalid frame range of subtitle {}: {}-{}, frames are numbered from 1",
+            id,
+            begin_frame,
+            end_frame
+        ));
+    }
+    if data.is_null() {
+        return job.fail(anyhow!("Subtitle {} has no image data", id));
+    }
+    let png = std::slice::from_raw_parts(data, len);
+    let image = match catch_unwind(AssertUnwindSafe(|| decode_png(png))) {
+        Ok(Ok(image)) => image,
+        Ok(Err(e)) => return job.fail(anyhow!("Failed to decode subtitle {}: {}", id, e)),
+        Err(_) => return job.fail(anyhow!("Panicked while decoding subtitle {}", id)),
+    };
+    job.options.lock().unwrap().subtitles.push(Subtitle {
+        subtitle_type,
+        id,
+        begin_flap: begin_frame,
+        end_flap: end_frame,
+        data: Arc::new(image),
+    });
+    return VILLAGERS_OK;
+}
+
+/// 运行压制, 阻塞至完成; callback可为NULL, 在压制线程中调用
+///
+/// 成功返回VILLAGERS_OK, 被取消时返回VILLAGERS_CANCELLED, 此时输出为截断但可播放的文件
+#[no_mangle]
+pub unsafe extern "C" fn villagers_job_run(
+    job: *mut VillagersJob,
+    callback: VillagersProgressCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let job = match job.as_ref() {
+        Some(job) => job,
+        None => return VILLAGERS_ERROR,
+    };
+    *job.error.lock().unwrap() = None;
+    let embedding = {
+        let options = job.options.lock().unwrap();
+        let mut builder = match EmbeddingBuilder::from_args(&options.args) {
+            Ok(builder) => builder,
+            Err(e) => return job.fail(e),
+        };
+        for subtitle in options.subtitles.iter() {
+            builder = builder.subtitle(subtitle.clone());
+        }
+        builder = builder.cancel_token(job.cancel.clone());
+        if let Some(callback) = callback {
+            let user_data = UserData(user_data);
+            builder = builder.on_progress(move |update: &ProgressUpdate| {
+                let progress = VillagersProgress {
+                    frames_done: update.frames_done,
+                    total_frames: update.total_frames,
+                    fps: update.fps,
+                    elapsed: update.elapsed,
+                    eta: update.eta.unwrap_or(-1.0),
+                    finished: update.finished as c_int,
+                };
+                callback(&progress, user_data.get());
+            });
+        }
+        match builder.build() {
+            Ok(embedding) => embedding,
+            Err(e) => return job.fail(e),
+        }
+    };
+    let result = catch_unwind(AssertUnwindSafe(|| embedding.run()));
+    return match result {
+        Ok(Ok(())) => VILLAGERS_OK,
+        Ok(Err(e)) if job.cancel.is_cancelled() => {
+            job.fail(e);
+            VILLAGERS_CANCELLED
+        }
+        Ok(Err(e)) => job.fail(e),
+        Err(_) => job.fail(anyhow!("Panicked while embedding")),
+    };
+}
+
+/// 请求停止正在运行的压制, 可在其他线程中调用; 取消后任务不能再次运行
+#[no_mangle]
+pub unsafe extern "C" fn villagers_job_cancel(job: *const VillagersJob) {
+    if let Some(job) = job.as_ref() {
+        job.cancel.cancel();
+    }
+}
+
+/// 最近一次出错的信息, 没有错误时返回NULL; 在下一次调用该任务的函数前有效
+#[no_mangle]
+pub unsafe extern "C" fn villagers_job_last_error(job: *const VillagersJob) -> *const c_char {
+    match job.as_ref() {
+        Some(job) => job
+            .error
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr()),
+        None => ptr::null(),
+    }
+}
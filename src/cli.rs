@@ -0,0 +1,86 @@
+use ::log::{debug, info};
+use ffmpeg_next::log;
+use flexi_logger::{opt_format, Logger};
+
+use crate::{
+    batch, cancel,
+    cmdline::Command,
+    config::{dump_config, parse_args},
+    contact_sheet, daemon,
+    pipeline::{self, Hooks},
+    preview,
+    progress::ProgressMode,
+    serve, verify, watch,
+};
+
+/// 命令行程序的入口, 供src/main.rs调用
+pub fn run() -> anyhow::Result<()> {
+    log::set_level(log::Level::Info);
+    ffmpeg_next::init().unwrap();
+    let (arg, matches, config) = parse_args()?;
+    if let Some(Command::DumpConfig) = &arg.command {
+        return dump_config(&matches, config.as_ref());
+    }
+    let progress_mode = arg.progress.resolve();
+    // 在终端中显示进度条时只输出警告与错误, 避免日志打断进度条; 标准输出被重定向时保留日志
+    let level = if arg.debug {
+        "debug"
+    } else if arg.command.is_none()
+        && progress_mode == ProgressMode::Bar
+        && atty::is(atty::Stream::Stdout)
+    {
+        "warn"
+    } else {
+        "info"
+    };
+    let logger = Logger::try_with_str(level).unwrap().format(opt_format);
+    // JSON进度事件占用标准输出时, 日志改为输出到标准错误
+    let logger = if progress_mode == ProgressMode::Json && arg.progress_fd.is_none() {
+        logger.log_to_stderr()
+    } else {
+        logger.log_to_stdout()
+    };
+    logger.start().expect("Failed to start logger!");
+    if let Some(config) = &config {
+        info!("Using config {}", config.path.display());
+    }
+    debug!("{:?}", arg);
+    match &arg.command {
+        Some(Command::Verify(verify_arg)) => return verify::run(&arg, verify_arg),
+        Some(Command::Preview(preview_arg)) => return preview::run(&arg, preview_arg),
+        Some(Command::ContactSheet(sheet_arg)) => return contact_sheet::run(&arg, sheet_arg),
+        Some(Command::Serve(serve_arg)) => return serve::run(&arg, serve_arg),
+        Some(Command::Batch(batch_arg)) => return batch::run(batch_arg),
+        Some(Command::Watch(watch_arg)) => return watch::run(watch_arg),
+        Some(Command::Daemon(daemon_arg)) => return daemon::run(daemon_arg),
+        Some(Command::DumpConfig) | None => {}
+    }
+    // {
+    //     let mut src = read_image(&PathBuf::from("./images/out001.png")).unwrap();
+    //     let sub = read_image(&PathBuf::from(
+    //         "./subtitle-images/major-subtitle-1-1-100.png",
+    //     ))
+    //     .unwrap();
+    //     let wid = src.width() as _;
+    //     let hei = src.height() as _;
+    //     let ls = unsafe { (*src.as_ptr()).linesize[0] };
+    //     let subls = unsafe { (*sub.as_ptr()).linesize[0] };
+
+    //     raw_embed(
+    //         src.data_mut(0),
+    //         wid,
+    //         hei,
+    //         ls,
+    //         sub.data(0),
+    //         sub.width() as _,
+    //         sub.height() as _,
+    //         subls,
+    //         embedder::Offset::MajorBottom(50),
+    //     );
+    //     save_png(&src, Path::new("1.png")).unwrap();
+    //     // std::os::raw::sy
+    //     panic!("qaq");
+    // }
+    cancel::install();
+    return pipeline::run(&arg, None, Hooks::default());
+}
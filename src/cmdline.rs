@@ -1,4 +1,13 @@
-use clap::Parser;
+use std::str::FromStr;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    audio::AudioCodec,
+    mapping::StreamMap,
+    progress::ProgressMode,
+    trim::{parse_time, TimeRange},
+};
 #[derive(Parser, Debug)]
 #[clap(version, about, long_about = None, before_help = "Villager's Embedding Tools\n农民压制工具升级版：村民压制工具")]
 pub struct InputArg {
@@ -8,9 +17,14 @@ pub struct InputArg {
         short,
         long,
         // default_value = "lec.mp4",
-        help = "输入视频文件名 (ffmpeg所支持的格式)"
+        help = "输入视频文件名 (ffmpeg所支持的格式), 多次指定时按顺序拼接"
     )]
-    pub input: String,
+    pub input: Vec<String>,
+    #[clap(
+        long,
+        help = "输入视频列表文件, 每行一个文件名, 追加在--input之后拼接"
+    )]
+    pub input_list: Option<String>,
     #[clap(
         short,
         long,
@@ -22,11 +36,18 @@ pub struct InputArg {
         short,
         long,
         default_value = "subtitle-images",
-        help = "字幕图片文件夹"
+        help = "字幕图片文件夹, 或包含字幕图片的zip/tar压缩包"
     )]
     pub subtitle_files: String,
     #[clap(short, long, default_value = "veryfast", help = "libx264编码预设")]
     pub x264_preset: String,
+    #[clap(
+        long,
+        hide = true,
+        default_value = "zerolatency",
+        help = "libx264的tune参数, none表示不指定; 供测试编码器的延迟输出"
+    )]
+    pub x264_tune: String,
     #[clap(
         short,
         long,
@@ -55,4 +76,265 @@ pub struct InputArg {
         help = "视频输出码率，默认使用解码器码率"
     )]
     pub bitrate: Option<usize>,
+    #[clap(
+        long,
+        default_value_t = 203.0,
+        help = "HDR视频中SDR字幕白色所对应的亮度 (nits)"
+    )]
+    pub reference_white: f64,
+    #[clap(
+        long,
+        help = "压制字幕的视频流编号，默认自动选择"
+    )]
+    pub video_stream: Option<usize>,
+    #[clap(
+        long = "map",
+        help = "选择输出的流, 可多次指定, 按顺序输出: 编号(1), 类型(v/a/s/d/t), 类型中的第n个(a:0), 语言(lang=jpn, s:lang=chi), 以-开头表示丢弃"
+    )]
+    pub map: Vec<StreamMap>,
+    #[clap(
+        long,
+        help = "音频编码器(copy/aac/opus/flac), 可用 流编号=编码器 对单条流指定, 默认直接复制"
+    )]
+    pub audio_codec: Vec<PerStream<AudioCodec>>,
+    #[clap(
+        long,
+        help = "音频输出码率, 可用 流编号=码率 对单条流指定, 默认192000"
+    )]
+    pub audio_bitrate: Vec<PerStream<usize>>,
+    #[clap(
+        long,
+        allow_hyphen_values = true,
+        help = "EBU R128响度标准化的目标响度(LUFS, 如-23), 可用 流编号=响度 对单条流指定, 需要重新编码音频"
+    )]
+    pub loudness: Vec<PerStream<f64>>,
+    #[clap(long, help = "覆盖输出文件的标题")]
+    pub title: Option<String>,
+    #[clap(
+        long,
+        help = "覆盖流的语言标签, 可用 流编号=语言 对单条流指定"
+    )]
+    pub language: Vec<PerStream<String>>,
+    #[clap(
+        long,
+        help = "在输出文件的metadata中记录所压制的字幕 (burned_subtitles标签)"
+    )]
+    pub subtitle_tag: Option<String>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_time),
+        help = "从该时间开始输出 (秒, 或 HH:MM:SS.ms)"
+    )]
+    pub start: Option<f64>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_time),
+        help = "在该时间结束输出 (秒, 或 HH:MM:SS.ms)"
+    )]
+    pub end: Option<f64>,
+    #[clap(
+        long,
+        help = "保留的时间段, 可多次指定, 形如 10-20.5, 01:00-01:30:00, 300-; 字幕仍按原视频的帧号"
+    )]
+    pub keep: Vec<TimeRange>,
+    #[clap(
+        long,
+        help = "输出低分辨率的预览版, 指定画面高度 (如540), 字幕等比缩小并使用ultrafast预设"
+    )]
+    pub proxy: Option<u32>,
+    #[clap(
+        long,
+        default_value = "auto",
+        help = "进度输出方式: auto (标准输出与标准错误均为终端时显示进度条, 否则打印日志), bar (在标准错误上显示进度条, 标准输出为终端时只输出警告与错误日志), log (日志), json (每行一个JSON事件, 供前端与调度程序读取)"
+    )]
+    pub progress: ProgressMode,
+    #[clap(
+        long,
+        help = "JSON进度事件写入的文件描述符, 默认写入标准输出 (此时日志改为输出到标准错误)"
+    )]
+    pub progress_fd: Option<i32>,
+    #[clap(long, help = "压制结束后写入JSON格式的汇总报告")]
+    pub summary: Option<String>,
+    #[clap(
+        long,
+        help = "可恢复压制: 每块帧写为独立分段并记录进度 (位于 输出文件名.parts), 中断后以相同参数再次运行时从最后完成的分段继续; 不支持拼接与剪辑"
+    )]
+    pub resume: bool,
+    #[clap(
+        long,
+        help = "项目配置文件 (TOML), 默认读取当前目录下的villagers.toml; 命令行参数优先, 配置中开启的开关可用--no-<开关名>关闭; 文件路径相对于配置文件所在目录"
+    )]
+    pub config: Option<String>,
+    #[clap(
+        long,
+        conflicts_with = "config",
+        help = "不读取配置文件, 忽略当前目录下的villagers.toml"
+    )]
+    pub no_config: bool,
+    #[clap(long, help = "使用配置文件中[profiles.<名称>]下的配置, 如release, preview")]
+    pub profile: Option<String>,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// 子命令, 缺省时压制字幕
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 逐帧比较输入与输出视频 (使用--input, --output, --subtitle-files等参数)
+    Verify(VerifyArg),
+    /// 渲染单帧预览并保存为PNG
+    Preview(PreviewArg),
+    /// 为每条字幕渲染首帧缩略图, 生成HTML总览页
+    ContactSheet(ContactSheetArg),
+    /// 启动本地HTTP服务, 在浏览器中逐帧检查字幕位置
+    Serve(ServeArg),
+    /// 以TOML格式打印合并配置文件与命令行后实际生效的参数
+    DumpConfig,
+    /// 按任务文件批量压制, 失败的任务不影响其余任务
+    Batch(BatchArg),
+    /// 监视收件箱文件夹, 自动压制放入的任务文件夹
+    Watch(WatchArg),
+    /// 启动本地HTTP任务接口, 供其他程序提交, 查询与取消压制任务
+    Daemon(DaemonArg),
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArg {
+    #[clap(
+        long,
+        default_value_t = 35.0,
+        help = "字幕区域以外PSNR低于该值(dB)的帧视为不一致"
+    )]
+    pub psnr_threshold: f64,
+    #[clap(long, help = "逐帧报告的输出文件 (CSV)")]
+    pub report: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct PreviewArg {
+    #[clap(long, required_unless_present = "time", help = "预览的帧号 (从1开始)")]
+    pub frame: Option<i64>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_time),
+        conflicts_with = "frame",
+        help = "预览的时间 (秒, 或 HH:MM:SS.ms)"
+    )]
+    pub time: Option<f64>,
+    #[clap(long, help = "输出PNG文件名, 默认为 preview-帧号.png")]
+    pub image: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ContactSheetArg {
+    #[clap(
+        long,
+        default_value = "contact-sheet",
+        help = "缩略图与index.html的输出文件夹"
+    )]
+    pub dir: String,
+    #[clap(long, default_value_t = 480, help = "缩略图宽度")]
+    pub thumb_width: u32,
+    #[clap(long, default_value_t = 4, help = "总览页每行的缩略图数")]
+    pub columns: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArg {
+    #[clap(
+        long,
+        default_value = "127.0.0.1:8080",
+        help = "监听地址, 局域网访问可使用 0.0.0.0:8080"
+    )]
+    pub listen: String,
+}
+
+#[derive(Args, Debug)]
+pub struct BatchArg {
+    #[clap(
+        help = "任务文件 (TOML), [defaults]为公共参数, 每个[[job]]给出input, subtitle-files, output等参数"
+    )]
+    pub job_file: String,
+    #[clap(short, long, default_value_t = 1, help = "同时运行的任务数")]
+    pub jobs: usize,
+    #[clap(long, default_value = "batch-logs", help = "各任务日志的输出文件夹")]
+    pub log_dir: String,
+    #[clap(
+        long,
+        default_value = "batch-report.json",
+        help = "各任务状态报告的输出文件 (JSON)"
+    )]
+    pub report: String,
+}
+
+#[derive(Args, Debug)]
+pub struct WatchArg {
+    #[clap(
+        help = "收件箱文件夹, 每个子文件夹为一个任务: 含job.toml任务清单, 或一个视频与一个字幕文件夹"
+    )]
+    pub inbox: String,
+    #[clap(long, default_value = "processing", help = "处理中任务的存放文件夹")]
+    pub work_dir: String,
+    #[clap(long, default_value = "done", help = "成功任务的存放文件夹")]
+    pub done_dir: String,
+    #[clap(long, default_value = "failed", help = "失败任务的存放文件夹")]
+    pub failed_dir: String,
+    #[clap(long, default_value_t = 5, help = "扫描收件箱的间隔 (秒)")]
+    pub interval: u64,
+    #[clap(
+        long,
+        default_value_t = 10,
+        help = "任务文件夹在该时长 (秒) 内无修改才开始处理, 避免处理复制中的文件"
+    )]
+    pub settle: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct DaemonArg {
+    #[clap(long, default_value = "127.0.0.1:8090", help = "监听地址")]
+    pub listen: String,
+    #[clap(short, long, default_value_t = 1, help = "同时运行的任务数")]
+    pub jobs: usize,
+    #[clap(long, default_value = "daemon-logs", help = "各任务日志的输出文件夹")]
+    pub log_dir: String,
+}
+
+/// 可按流指定的参数, 形如 `aac` (对全部音频流生效) 或 `1=aac` (仅对输入流1生效)
+#[derive(Debug, Clone)]
+pub struct PerStream<T> {
+    pub stream: Option<usize>,
+    pub value: T,
+}
+
+impl<T: FromStr> FromStr for PerStream<T>
+where
+    T::Err: std::fmt::Display,
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (stream, value) = match s.split_once('=') {
+            Some((stream, value)) => (
+                Some(
+                    stream
+                        .parse::<usize>()
+                        .map_err(|e| format!("Invalid stream index {}: {}", stream, e))?,
+                ),
+                value,
+            ),
+            None => (None, s),
+        };
+        let value = value.parse::<T>().map_err(|e| e.to_string())?;
+        return Ok(Self { stream, value });
+    }
+}
+
+/// 取对某条流生效的参数, 指定了流编号的优先
+pub fn lookup<T: Clone>(options: &[PerStream<T>], stream: usize) -> Option<T> {
+    options
+        .iter()
+        .rev()
+        .find(|o| o.stream == Some(stream))
+        .or(options.iter().rev().find(|o| o.stream.is_none()))
+        .map(|o| o.value.clone())
 }
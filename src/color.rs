@@ -0,0 +1,338 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use ffmpeg_next::{
+    codec::packet::side_data::Type as SideDataType,
+    color::{Primaries, TransferCharacteristic},
+    format::Pixel,
+    frame::Video,
+    Stream,
+};
+use ffmpeg_sys_next::{
+    av_stream_new_side_data, AVContentLightMetadata, AVMasteringDisplayMetadata,
+    AVPacketSideDataType, AVRational, AVStream,
+};
+use log::info;
+
+use crate::subtitle::Subtitle;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transfer {
+    Sdr,
+    Pq,
+    Hlg,
+}
+
+/// 描述合成时所使用的像素格式以及SDR字幕到视频传递函数的映射方式
+#[derive(Clone, Debug)]
+pub struct ColorMapping {
+    pub format: Pixel,
+    pub transfer: Transfer,
+    pub wide_gamut: bool,
+    pub reference_white: f64,
+}
+
+pub fn bit_depth(format: Pixel) -> i32 {
+    match format.descriptor() {
+        Some(desc) => unsafe { (*desc.as_ptr()).comp[0].depth },
+        None => 8,
+    }
+}
+
+impl ColorMapping {
+    pub fn new(
+        source_format: Pixel,
+        transfer: TransferCharacteristic,
+        primaries: Primaries,
+        reference_white: f64,
+    ) -> Self {
+        let format = if bit_depth(source_format) > 8 {
+            Pixel::RGB48LE
+        } else {
+            Pixel::RGB24
+        };
+        let transfer = match transfer {
+            TransferCharacteristic::SMPTE2084 => Transfer::Pq,
+            TransferCharacteristic::ARIB_STD_B67 => Transfer::Hlg,
+            _ => Transfer::Sdr,
+        };
+        Self {
+            format,
+            transfer,
+            wide_gamut: primaries == Primaries::BT2020,
+            reference_white,
+        }
+    }
+    pub fn is_high_depth(&self) -> bool {
+        self.format == Pixel::RGB48LE
+    }
+    // 将8bit SDR RGB值映射到16bit的目标传递函数
+    fn map_pixel(&self, rgb: [u8; 3]) -> [u16; 3] {
+        if self.transfer == Transfer::Sdr && !self.wide_gamut {
+            return rgb.map(|v| v as u16 * 257);
+        }
+        // BT.1886, gamma 2.4
+        let mut linear = rgb.map(|v| (v as f64 / 255.0).powf(2.4));
+        if self.wide_gamut {
+            let [r, g, b] = linear;
+            linear = [
+                0.6274 * r + 0.3293 * g + 0.0433 * b,
+                0.0691 * r + 0.9195 * g + 0.0114 * b,
+                0.0164 * r + 0.0880 * g + 0.8956 * b,
+            ];
+        }
+        let encoded = linear.map(|v| {
+            let v = v.max(0.0);
+            match self.transfer {
+                Transfer::Sdr => v.powf(1.0 / 2.4),
+                Transfer::Pq => pq_oetf(v * self.reference_white),
+                Transfer::Hlg => hlg_oetf(v * self.reference_white),
+            }
+        });
+        return encoded.map(|v| (v.clamp(0.0, 1.0) * 65535.0).round() as u16);
+    }
+    /// 将RGB24的字幕图片转换为合成所使用的像素格式
+    pub fn map_image(&self, image: &Video) -> anyhow::Result<Video> {
+        let width = image.width();
+        let height = image.height();
+        let mut mapped = Video::new(self.format, width, height);
+        let src_linesize = image.stride(0);
+        let dst_linesize = mapped.stride(0);
+        let src = image.data(0);
+        let dst = mapped.data_mut(0);
+        for r in 0..height as usize {
+            for c in 0..width as usize {
+                let src_base = r * src_linesize + c * 3;
+                let dst_base = r * dst_linesize + c * 6;
+                let mapped_pixel =
+                    self.map_pixel([src[src_base], src[src_base + 1], src[src_base + 2]]);
+                for x in 0..3 {
+                    dst[dst_base + 2 * x..dst_base + 2 * x + 2]
+                        .copy_from_slice(&mapped_pixel[x].to_le_bytes());
+                }
+            }
+        }
+        return Ok(mapped);
+    }
+}
+
+// nits -> PQ (SMPTE ST 2084)
+fn pq_oetf(nits: f64) -> f64 {
+    const M1: f64 = 0.1593017578125;
+    const M2: f64 = 78.84375;
+    const C1: f64 = 0.8359375;
+    const C2: f64 = 18.8515625;
+    const C3: f64 = 18.6875;
+    let y = (nits / 10000.0).powf(M1);
+    return ((C1 + C2 * y) / (1.0 + C3 * y)).powf(M2);
+}
+
+// nits -> HLG (ARIB STD-B67), 以1000nits显示器, 系统gamma 1.2计算
+fn hlg_oetf(nits: f64) -> f64 {
+    const A: f64 = 0.17883277;
+    const B: f64 = 0.28466892;
+    const C: f64 = 0.55991073;
+    let e = (nits / 1000.0).powf(1.0 / 1.2);
+    if e <= 1.0 / 12.0 {
+        return (3.0 * e).sqrt();
+    } else {
+        return A * (12.0 * e - B).ln() + C;
+    }
+}
+
+pub fn map_subtitles(subtitles: &mut Vec<Subtitle>, mapping: &ColorMapping) -> anyhow::Result<()> {
+    if !mapping.is_high_depth() {
+        return Ok(());
+    }
+    info!(
+        "Mapping subtitles into {:?}, transfer {:?}, reference white {} nits",
+        mapping.format, mapping.transfer, mapping.reference_white
+    );
+    for subtitle in subtitles.iter_mut() {
+        let mapped = mapping
+            .map_image(&subtitle.data)
+            .map_err(|e| anyhow!("Failed to map subtitle {}: {}", subtitle.id, e))?;
+        subtitle.data = Arc::new(mapped);
+    }
+    return Ok(());
+}
+
+/// 视频流上的HDR静态元数据
+#[derive(Default, Clone)]
+pub struct HdrMetadata {
+    pub mastering_display: Option<Vec<u8>>,
+    pub content_light_level: Option<Vec<u8>>,
+}
+
+impl HdrMetadata {
+    pub fn from_stream(stream: &Stream) -> Self {
+        let mut result = Self::default();
+        for side_data in stream.side_data() {
+            match side_data.kind() {
+                SideDataType::MasteringDisplayMetadata => {
+                    result.mastering_display = Some(side_data.data().to_vec());
+                }
+                SideDataType::ContentLightLevel => {
+                    result.content_light_level = Some(side_data.data().to_vec());
+                }
+                _ => {}
+            }
+        }
+        return result;
+    }
+    pub fn is_empty(&self) -> bool {
+        self.mastering_display.is_none() && self.content_light_level.is_none()
+    }
+    pub unsafe fn copy_to_stream(&self, stream: *mut AVStream) -> anyhow::Result<()> {
+        let entries = [
+            (
+                AVPacketSideDataType::AV_PKT_DATA_MASTERING_DISPLAY_METADATA,
+                &self.mastering_display,
+            ),
+            (
+                AVPacketSideDataType::AV_PKT_DATA_CONTENT_LIGHT_LEVEL,
+                &self.content_light_level,
+            ),
+        ];
+        for (kind, data) in entries {
+            if let Some(data) = data {
+                let dst = av_stream_new_side_data(stream, kind, data.len() as _);
+                if dst.is_null() {
+                    return Err(anyhow!("Failed to allocate side data {:?}", kind));
+                }
+                std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+            }
+        }
+        return Ok(());
+    }
+    /// 生成libx264的x264-params, 使编码后的码流带有对应的SEI
+    pub fn x264_params(&self) -> Option<String> {
+        let mut params = vec![];
+        if let Some(data) = &self.mastering_display {
+            if data.len() >= std::mem::size_of::<AVMasteringDisplayMetadata>() {
+                let meta = unsafe {
+                    std::ptr::read_unaligned(data.as_ptr() as *const AVMasteringDisplayMetadata)
+                };
+                let chroma = |v: AVRational| (q2d(v) * 50000.0).round() as i64;
+                let luma = |v: AVRational| (q2d(v) * 10000.0).round() as i64;
+                if meta.has_primaries != 0 && meta.has_luminance != 0 {
+                    let [r, g, b] = meta.display_primaries;
+                    params.push(format!(
+                        "mastering-display=G({},{})B({},{})R({},{})WP({},{})L({},{})",
+                        chroma(g[0]),
+                        chroma(g[1]),
+                        chroma(b[0]),
+                        chroma(b[1]),
+                        chroma(r[0]),
+                        chroma(r[1]),
+                        chroma(meta.white_point[0]),
+                        chroma(meta.white_point[1]),
+                        luma(meta.max_luminance),
+                        luma(meta.min_luminance),
+                    ));
+                }
+            }
+        }
+        if let Some(data) = &self.content_light_level {
+            if data.len() >= std::mem::size_of::<AVContentLightMetadata>() {
+                let meta = unsafe {
+                    std::ptr::read_unaligned(data.as_ptr() as *const AVContentLightMetadata)
+                };
+                params.push(format!("cll={},{}", meta.MaxCLL, meta.MaxFALL));
+            }
+        }
+        if params.is_empty() {
+            return None;
+        }
+        return Some(params.join(":"));
+    }
+}
+
+#[inline]
+fn q2d(v: AVRational) -> f64 {
+    if v.den == 0 {
+        return 0.0;
+    }
+    return v.num as f64 / v.den as f64;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(transfer: Transfer, wide_gamut: bool, reference_white: f64) -> ColorMapping {
+        ColorMapping {
+            format: Pixel::RGB48LE,
+            transfer,
+            wide_gamut,
+            reference_white,
+        }
+    }
+
+    fn assert_code(actual: u16, expected: f64) {
+        let expected = expected * 65535.0;
+        assert!(
+            (actual as f64 - expected).abs() <= 70.0,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn oetf_reference_points() {
+        assert!(pq_oetf(0.0) < 1e-6);
+        assert!((pq_oetf(10000.0) - 1.0).abs() < 1e-9);
+        // BT.2408: 203nits的参考白在PQ中约为58%, 在HLG中为75%
+        assert!((pq_oetf(203.0) - 0.5808).abs() < 1e-3);
+        assert_eq!(hlg_oetf(0.0), 0.0);
+        assert!((hlg_oetf(203.0) - 0.75).abs() < 1e-3);
+        assert!((hlg_oetf(1000.0) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn black_maps_to_zero() {
+        for transfer in [Transfer::Sdr, Transfer::Pq, Transfer::Hlg] {
+            for wide_gamut in [false, true] {
+                let mapping = mapping(transfer, wide_gamut, 203.0);
+                assert_eq!(mapping.map_pixel([0, 0, 0]), [0, 0, 0]);
+            }
+        }
+    }
+
+    #[test]
+    fn reference_white_maps_to_code_value() {
+        assert_eq!(
+            mapping(Transfer::Sdr, false, 203.0).map_pixel([255, 255, 255]),
+            [65535; 3]
+        );
+        for wide_gamut in [false, true] {
+            let pq = mapping(Transfer::Pq, wide_gamut, 203.0).map_pixel([255, 255, 255]);
+            let hlg = mapping(Transfer::Hlg, wide_gamut, 203.0).map_pixel([255, 255, 255]);
+            for x in 0..3 {
+                assert_code(pq[x], 0.5808);
+                assert_code(hlg[x], 0.75);
+            }
+        }
+    }
+
+    #[test]
+    fn clamps_at_top_of_range() {
+        assert_eq!(
+            mapping(Transfer::Pq, false, 10000.0).map_pixel([255, 255, 255]),
+            [65535; 3]
+        );
+        assert_eq!(
+            mapping(Transfer::Pq, false, 20000.0).map_pixel([255, 255, 255]),
+            [65535; 3]
+        );
+        assert_eq!(
+            mapping(Transfer::Hlg, true, 4000.0).map_pixel([255, 255, 255]),
+            [65535; 3]
+        );
+        // 超出范围的分量单独截断, 其余分量不受影响
+        let red = mapping(Transfer::Pq, true, 20000.0).map_pixel([255, 0, 0]);
+        assert_eq!(red[0], 65535);
+        assert!(red[1] < 65535 && red[2] < 65535);
+    }
+}
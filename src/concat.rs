@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use ffmpeg_next::{codec, decoder, format::context::Input, Error, Rational, Stream};
+use ffmpeg_sys_next::{avcodec_parameters_to_context, AV_NOPTS_VALUE, AV_TIME_BASE};
+
+/// 读取输入列表文件, 每行一个文件名, 也支持ffmpeg concat格式的 `file 'xxx.mp4'`
+///
+/// 空行与`#`开头的行会被忽略, 相对路径相对于列表文件所在的目录
+pub fn read_input_list(path: &Path) -> anyhow::Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read input list {}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut result = vec![];
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let name = match line.strip_prefix("file ") {
+            Some(rest) => rest.trim().trim_matches('\'').trim_matches('"'),
+            None => line,
+        };
+        result.push(base.join(name).to_string_lossy().to_string());
+    }
+    return Ok(result);
+}
+
+/// 输入文件的起始时间(秒)
+pub fn input_origin(ctx: &Input) -> f64 {
+    let start_time = unsafe { (*ctx.as_ptr()).start_time };
+    if start_time == AV_NOPTS_VALUE {
+        return 0.0;
+    }
+    return start_time as f64 / AV_TIME_BASE as f64;
+}
+
+/// 输入文件的时长(秒)
+pub fn input_duration(ctx: &Input) -> Option<f64> {
+    let duration = unsafe { (*ctx.as_ptr()).duration };
+    if duration == AV_NOPTS_VALUE || duration <= 0 {
+        return None;
+    }
+    return Some(duration as f64 / AV_TIME_BASE as f64);
+}
+
+/// 检查后续输入文件与第一个文件的流是否一致
+pub fn check_streams(
+    first: &Input,
+    other: &Input,
+    selected: &[usize],
+    name: &str,
+) -> anyhow::Result<()> {
+    for &idx in selected {
+        let expected = first.stream(idx).unwrap();
+        let actual = other
+            .stream(idx)
+            .ok_or(anyhow!("{}: missing stream {}", name, idx))?;
+        if expected.codec().medium() != actual.codec().medium() {
+            return Err(anyhow!(
+                "{}: stream {} is {:?}, expected {:?}",
+                name,
+                idx,
+                actual.codec().medium(),
+                expected.codec().medium()
+            ));
+        }
+        if expected.codec().medium() != ffmpeg_next::media::Type::Video
+            && expected.codec().id() != actual.codec().id()
+        {
+            return Err(anyhow!(
+                "{}: stream {} is encoded with {:?}, expected {:?}",
+                name,
+                idx,
+                actual.codec().id(),
+                expected.codec().id()
+            ));
+        }
+    }
+    return Ok(());
+}
+
+pub fn open_video_decoder(stream: &Stream) -> anyhow::Result<decoder::Video> {
+    let context_decoder = {
+        let parameters = stream.parameters();
+        let mut context = codec::context::Context::new();
+
+        unsafe {
+            match avcodec_parameters_to_context(context.as_mut_ptr(), parameters.as_ptr()) {
+                e if e < 0 => Err(Error::from(e)),
+                _ => Ok(context),
+            }
+        }?
+    };
+    return Ok(context_decoder.decoder().video()?);
+}
+
+/// 某个输入文件在拼接后时间轴上的偏移
+#[derive(Clone, Copy, Debug)]
+pub struct PartOffset {
+    pub seconds: f64,
+}
+
+impl PartOffset {
+    pub fn shift(&self, ts: i64, time_base: Rational) -> i64 {
+        ts + (self.seconds / f64::from(time_base)).round() as i64
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use toml::{value::Table, Value};
+
+use crate::cmdline::InputArg;
+
+// 未指定--config时, 当前目录下存在该文件则自动读取
+pub const DEFAULT_CONFIG: &str = "villagers.toml";
+
+// 不能写在配置文件中的参数
+const RESERVED: [&str; 5] = ["help", "version", "config", "no_config", "profile"];
+
+// 值为文件路径的参数, 配置文件中的相对路径相对于配置文件所在目录
+const PATH_KEYS: [&str; 5] = ["input", "input_list", "output", "subtitle_files", "summary"];
+
+/// 实际读取的配置文件, 及其中未被命令行覆盖而实际采用的配置项
+pub struct Config {
+    pub path: PathBuf,
+    pub table: Table,
+}
+
+/// 读取配置文件, 返回合并了profile后的参数表
+///
+/// 顶层的键为通用配置, `[profiles.<name>]` 中的键覆盖通用配置
+fn load_config(path: &Path, profile: Option<&str>) -> anyhow::Result<Table> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read config {}: {}", path.display(), e))?;
+    let mut table = content
+        .parse::<Value>()
+        .map_err(|e| anyhow!("Invalid config {}: {}", path.display(), e))?
+        .as_table()
+        .cloned()
+        .ok_or(anyhow!("Invalid config {}", path.display()))?;
+    let profiles = table.remove("profiles");
+    if let Some(name) = profile {
+        let overrides = profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+            .and_then(|profile| profile.as_table())
+            .ok_or(anyhow!("Profile {} not found in {}", name, path.display()))?;
+        for (key, value) in overrides.iter() {
+            table.insert(key.clone(), value.clone());
+        }
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for (key, value) in table.iter_mut() {
+        if PATH_KEYS.contains(&key.replace('-', "_").as_str()) {
+            resolve_path(value, dir);
+        }
+    }
+    return Ok(table);
+}
+
+/// 将相对路径改为相对于配置文件所在目录
+fn resolve_path(value: &mut Value, dir: &Path) {
+    match value {
+        Value::String(s) if Path::new(s.as_str()).is_relative() => {
+            *s = dir.join(s.as_str()).to_string_lossy().into_owned();
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| resolve_path(value, dir)),
+        _ => {}
+    }
+}
+
+/// 取出命令行中的 --no-<开关>, 用于关闭配置文件中开启的开关, 返回被关闭的参数id
+fn take_negated(command: &clap::Command, cli: &mut Vec<OsString>) -> Vec<String> {
+    let mut negated = vec![];
+    cli.retain(|arg| {
+        let name = match arg.to_str().and_then(|arg| arg.strip_prefix("--no-")) {
+            Some(name) => name,
+            None => return true,
+        };
+        match command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(name))
+        {
+            Some(arg) if !arg.is_takes_value_set() && !RESERVED.contains(&arg.get_id()) => {
+                negated.push(arg.get_id().to_string());
+                false
+            }
+            _ => true,
+        }
+    });
+    return negated;
+}
+
+/// 将配置项转为命令行参数, 数组展开为多次指定
+pub fn value_to_arg(key: &str, value: &Value) -> anyhow::Result<Vec<String>> {
+    let flag = format!("--{}", key.replace('_', "-"));
+    return Ok(match value {
+        Value::Boolean(true) => vec![flag],
+        Value::Boolean(false) => vec![],
+        Value::String(s) => vec![format!("{}={}", flag, s)],
+        Value::Integer(_) | Value::Float(_) => vec![format!("{}={}", flag, value)],
+        Value::Array(values) => {
+            let mut result = vec![];
+            for value in values.iter() {
+                result.extend(value_to_arg(key, value)?);
+            }
+            result
+        }
+        _ => return Err(anyhow!("Unsupported value for {}: {}", key, value)),
+    });
+}
+
+/// 解析命令行参数, 并合并项目配置文件; 命令行中给出的参数优先
+///
+/// 同时返回实际读取的配置文件, 未读取时为None
+pub fn parse_args() -> anyhow::Result<(InputArg, ArgMatches, Option<Config>)> {
+    return parse_args_from(std::env::args_os().collect());
+}
+
+fn parse_args_from(
+    mut cli: Vec<OsString>,
+) -> anyhow::Result<(InputArg, ArgMatches, Option<Config>)> {
+    let command = InputArg::command();
+    let negated = take_negated(&command, &mut cli);
+    let matches = command.clone().get_matches_from(&cli);
+    let path = match matches.value_of("config") {
+        Some(path) => Path::new(path).to_path_buf(),
+        None if !matches.is_present("no_config") && Path::new(DEFAULT_CONFIG).exists() => {
+            Path::new(DEFAULT_CONFIG).to_path_buf()
+        }
+        None => {
+            if matches.is_present("profile") {
+                return Err(anyhow!("--profile requires a config file"));
+            }
+            let arg = InputArg::from_arg_matches(&matches)?;
+            return Ok((arg, matches, None));
+        }
+    };
+    let table = load_config(&path, matches.value_of("profile"))?;
+    let mut applied = Table::new();
+    let mut argv = vec![cli[0].clone()];
+    for (key, value) in table.iter() {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .filter(|arg| !RESERVED.contains(&arg.get_id()))
+            .ok_or(anyhow!("Unknown key {} in {}", key, path.display()))?;
+        if matches.occurrences_of(arg.get_id()) > 0 || negated.iter().any(|id| id == arg.get_id()) {
+            continue;
+        }
+        argv.extend(value_to_arg(&long, value)?.into_iter().map(OsString::from));
+        applied.insert(key.clone(), value.clone());
+    }
+    argv.extend(cli[1..].iter().cloned());
+    let matches = command.get_matches_from(argv);
+    let arg = InputArg::from_arg_matches(&matches)?;
+    return Ok((
+        arg,
+        matches,
+        Some(Config {
+            path,
+            table: applied,
+        }),
+    ));
+}
+
+/// 以TOML格式打印合并后实际生效的参数
+pub fn dump_config(matches: &ArgMatches, config: Option<&Config>) -> anyhow::Result<()> {
+    let table = effective_table(matches, config);
+    print!(
+        "{}",
+        toml::to_string(&table).map_err(|e| anyhow!("Failed to serialize config: {}", e))?
+    );
+    return Ok(());
+}
+
+/// 合并后实际生效的参数表; 来自配置文件的值保留原本的类型
+fn effective_table(matches: &ArgMatches, config: Option<&Config>) -> Table {
+    let mut table = Table::new();
+    for arg in InputArg::command().get_arguments() {
+        let long = match arg.get_long() {
+            Some(long) if !RESERVED.contains(&arg.get_id()) => long,
+            _ => continue,
+        };
+        if !arg.is_takes_value_set() {
+            table.insert(
+                long.to_string(),
+                Value::Boolean(matches.is_present(arg.get_id())),
+            );
+            continue;
+        }
+        let original = config.and_then(|config| {
+            config
+                .table
+                .iter()
+                .find(|(key, _)| key.replace('_', "-") == long)
+        });
+        if let Some((_, value)) = original {
+            table.insert(long.to_string(), value.clone());
+            continue;
+        }
+        let values = match matches.values_of(arg.get_id()) {
+            Some(values) => values.map(parse_value).collect::<Vec<Value>>(),
+            None => continue,
+        };
+        if arg.is_multiple_occurrences_set() {
+            table.insert(long.to_string(), Value::Array(values));
+        } else if let Some(value) = values.into_iter().next() {
+            table.insert(long.to_string(), value);
+        }
+    }
+    return table;
+}
+
+fn parse_value(s: &str) -> Value {
+    if let Ok(v) = s.parse::<i64>() {
+        return Value::Integer(v);
+    }
+    if let Ok(v) = s.parse::<f64>() {
+        return Value::Float(v);
+    }
+    return Value::String(s.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, content: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("villagers-config-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("villagers.toml");
+        std::fs::write(&path, content).unwrap();
+        return path;
+    }
+
+    fn parse(path: &Path, args: &[&str]) -> (InputArg, ArgMatches, Option<Config>) {
+        let mut cli = vec![
+            OsString::from("villagers"),
+            OsString::from(format!("--config={}", path.display())),
+        ];
+        cli.extend(args.iter().map(OsString::from));
+        return parse_args_from(cli).unwrap();
+    }
+
+    const CONFIG: &str = r#"
+chunk_size = 500
+bottom-offset = 10
+debug = true
+title = "2024"
+output = "out/result.mp4"
+subtitle_files = "/data/subs"
+
+[profiles.preview]
+chunk_size = 50
+proxy = 540
+"#;
+
+    #[test]
+    fn profile_overrides_common_keys() {
+        let path = write_config("profile", CONFIG);
+        let table = load_config(&path, None).unwrap();
+        assert_eq!(table["chunk_size"].as_integer(), Some(500));
+        assert!(!table.contains_key("proxy"));
+        assert!(!table.contains_key("profiles"));
+        let table = load_config(&path, Some("preview")).unwrap();
+        assert_eq!(table["chunk_size"].as_integer(), Some(50));
+        assert_eq!(table["proxy"].as_integer(), Some(540));
+        assert_eq!(table["bottom-offset"].as_integer(), Some(10));
+        assert!(load_config(&path, Some("release")).is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let path = write_config("precedence", CONFIG);
+        let (arg, _, _) = parse(&path, &[]);
+        assert_eq!(arg.chunk_size, 500);
+        assert_eq!(arg.bottom_offset, 10);
+        assert!(arg.debug);
+        let (arg, _, _) = parse(&path, &["--chunk-size=20", "--profile=preview"]);
+        assert_eq!(arg.chunk_size, 20);
+        assert_eq!(arg.proxy, Some(540));
+        assert_eq!(arg.bottom_offset, 10);
+        let (arg, _, _) = parse(&path, &["--profile=preview"]);
+        assert_eq!(arg.chunk_size, 50);
+    }
+
+    #[test]
+    fn negated_flag_turns_off_config() {
+        let path = write_config("negated", CONFIG);
+        let (arg, _, config) = parse(&path, &["--no-debug"]);
+        assert!(!arg.debug);
+        assert!(!config.unwrap().table.contains_key("debug"));
+    }
+
+    #[test]
+    fn relative_paths_follow_config_dir() {
+        let path = write_config("paths", CONFIG);
+        let dir = path.parent().unwrap();
+        let (arg, _, _) = parse(&path, &[]);
+        assert_eq!(
+            Path::new(&arg.output),
+            dir.join("out").join("result.mp4").as_path()
+        );
+        assert_eq!(arg.subtitle_files, "/data/subs");
+        let (arg, _, _) = parse(&path, &["--output=cli.mp4"]);
+        assert_eq!(arg.output, "cli.mp4");
+    }
+
+    #[test]
+    fn dump_keeps_config_types() {
+        let path = write_config("dump", CONFIG);
+        let (_, matches, config) = parse(&path, &["--top-offset=7"]);
+        let table = effective_table(&matches, config.as_ref());
+        assert_eq!(table["title"].as_str(), Some("2024"));
+        assert_eq!(table["chunk-size"].as_integer(), Some(500));
+        assert_eq!(table["top-offset"].as_integer(), Some(7));
+        assert_eq!(table["debug"].as_bool(), Some(true));
+    }
+}
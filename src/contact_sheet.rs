@@ -0,0 +1,124 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use ffmpeg_next::{
+    format::Pixel,
+    frame::Video,
+    software::scaling::{self, Flags},
+};
+use log::info;
+
+use crate::{
+    cmdline::{ContactSheetArg, InputArg},
+    image::save_png,
+    preview::compose,
+    reader::FrameReader,
+    render::{init_render_data, subtitle_frames, RenderData},
+    subtitle::{load_subtitles, SubtitleType},
+};
+
+const SHEET_STYLE: &str =
+    "body{font-family:sans-serif}figure{margin:0}img{width:100%}figcaption{font-size:13px}";
+
+pub(crate) fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 为每条字幕渲染其首帧的缩略图, 并生成带标注的HTML索引页
+pub fn run(arg: &InputArg, sheet_arg: &ContactSheetArg) -> anyhow::Result<()> {
+    let input_name = arg
+        .input
+        .first()
+        .ok_or(anyhow!("No input file specified"))?;
+    let mut reader = FrameReader::open(input_name, arg.video_stream, Pixel::RGB24, None)?;
+    let fps = f64::from(reader.frame_rate());
+    let mut subtitles = load_subtitles(&PathBuf::from(&arg.subtitle_files))
+        .map_err(|e| anyhow!("Failed to read subtitles: {}\n", e))?;
+    info!("{} subtitles loaded.", subtitles.len());
+    let mut render_data = Vec::<RenderData>::new();
+    init_render_data(
+        &mut render_data,
+        &subtitles,
+        subtitle_frames(&subtitles).max(reader.frames()),
+        arg.top_offset,
+        arg.bottom_offset,
+    )?;
+    subtitles.sort_by_key(|subtitle| (subtitle.begin_flap, subtitle.id));
+
+    let dir = Path::new(&sheet_arg.dir);
+    std::fs::create_dir_all(dir)
+        .map_err(|e| anyhow!("Failed to create directory {}: {}", dir.display(), e))?;
+    let thumb_width = sheet_arg.thumb_width.min(reader.width());
+    let thumb_height = (reader.height() as u64 * thumb_width as u64 / reader.width() as u64) as u32;
+    let mut thumb_scaler = scaling::Context::get(
+        Pixel::RGB24,
+        reader.width(),
+        reader.height(),
+        Pixel::RGB24,
+        thumb_width,
+        thumb_height.max(1),
+        Flags::BILINEAR,
+    )?;
+
+    let mut html = String::new();
+    for subtitle in subtitles.iter() {
+        let frame_number = subtitle.begin_flap as i64;
+        let frame = reader.read_frame(frame_number)?;
+        let image = compose(&render_data, frame, frame_number, arg)?;
+        let mut thumb = Video::empty();
+        thumb_scaler
+            .run(&image, &mut thumb)
+            .map_err(|e| anyhow!("Failed to scale thumbnail: {}", e))?;
+        let track = match subtitle.subtitle_type {
+            SubtitleType::Major => "major",
+            SubtitleType::Minor => "minor",
+        };
+        let file_name = format!("{}-{}-{}.png", track, subtitle.id, frame_number);
+        save_png(&thumb, &dir.join(&file_name))?;
+        let caption = format!(
+            "#{} {} | 帧 {}-{} | {:.3}s-{:.3}s",
+            subtitle.id,
+            track,
+            subtitle.begin_flap,
+            subtitle.end_flap,
+            (subtitle.begin_flap - 1) as f64 / fps,
+            subtitle.end_flap as f64 / fps
+        );
+        html.push_str(&format!(
+            "<figure><img src=\"{}\"><figcaption>{}</figcaption></figure>\n",
+            file_name, caption
+        ));
+        info!(
+            "Subtitle {} {} rendered at frame {}",
+            track, subtitle.id, frame_number
+        );
+    }
+
+    let index_path = dir.join("index.html");
+    let mut index = std::fs::File::create(&index_path)
+        .map_err(|e| anyhow!("Failed to create {}: {}", index_path.display(), e))?;
+    writeln!(index, "<!DOCTYPE html>")?;
+    writeln!(
+        index,
+        "<html><head><meta charset=\"utf-8\"><title>{}</title>",
+        escape_html(input_name)
+    )?;
+    writeln!(
+        index,
+        "<style>body{{display:grid;grid-template-columns:repeat({},1fr);gap:8px}}{}</style>",
+        sheet_arg.columns, SHEET_STYLE
+    )?;
+    writeln!(index, "</head><body>\n{}</body></html>", html)?;
+    info!(
+        "Contact sheet of {} subtitles saved to {}",
+        subtitles.len(),
+        index_path.display()
+    );
+    return Ok(());
+}
@@ -0,0 +1,402 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, ExitStatus},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+use toml::value::Table;
+
+use crate::{
+    cmdline::DaemonArg,
+    jobs::{start_job, Job},
+    serve::respond,
+};
+
+// 由守护进程接管的参数, 提交任务时不能指定
+const RESERVED: [&str; 3] = ["progress", "progress-fd", "no-config"];
+// 请求体上限, 任务参数远小于此
+const MAX_BODY: usize = 64 * 1024;
+// 连接的读写超时, 避免空闲连接一直占用处理线程
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum State {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    id: u64,
+    job: Job,
+    state: State,
+    // 任务最近一次输出的JSON进度事件
+    progress: Option<Value>,
+    submitted: f64,
+    started: Option<f64>,
+    finished: Option<f64>,
+    exit_code: Option<i32>,
+    error: Option<String>,
+    log: String,
+    #[serde(skip)]
+    child: Option<Child>,
+    #[serde(skip)]
+    cancel: bool,
+}
+
+#[derive(Default)]
+struct Queue {
+    entries: Vec<Entry>,
+    next_id: u64,
+}
+
+impl Queue {
+    fn get_mut(&mut self, id: u64) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.id == id)
+    }
+}
+
+type Shared = Arc<(Mutex<Queue>, Condvar)>;
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |time| time.as_secs_f64())
+}
+
+/// 结束任务进程; unix下发送SIGTERM, 使其写完当前块并留下可播放的文件
+#[cfg(unix)]
+fn stop(child: &mut Child) -> std::io::Result<()> {
+    match unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+fn stop(child: &mut Child) -> std::io::Result<()> {
+    child.kill()
+}
+
+/// 运行一个任务直到结束, 期间持续更新进度
+fn execute(shared: &Shared, id: u64, job: &Job, log_path: &Path) -> anyhow::Result<ExitStatus> {
+    let (lock, _) = &**shared;
+    let mut child = start_job(job, log_path)?;
+    let stdout = child.stdout.take().unwrap();
+    {
+        let mut queue = lock.lock().unwrap();
+        let entry = queue.get_mut(id).unwrap();
+        // 启动期间收到的取消请求
+        if entry.cancel {
+            let _ = stop(&mut child);
+        }
+        entry.child = Some(child);
+    }
+    for line in BufReader::new(stdout).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        match serde_json::from_str::<Value>(&line) {
+            Ok(event) => lock.lock().unwrap().get_mut(id).unwrap().progress = Some(event),
+            Err(_) => debug!("Job {}: {}", id, line),
+        }
+    }
+    let mut child = lock
+        .lock()
+        .unwrap()
+        .get_mut(id)
+        .unwrap()
+        .child
+        .take()
+        .unwrap();
+    return child
+        .wait()
+        .map_err(|e| anyhow!("Failed to wait for job {}: {}", id, e));
+}
+
+fn worker(shared: Shared) {
+    let (lock, condvar) = &*shared;
+    loop {
+        let (id, job, log_path) = {
+            let mut queue = condvar
+                .wait_while(lock.lock().unwrap(), |queue| {
+                    !queue
+                        .entries
+                        .iter()
+                        .any(|entry| entry.state == State::Queued)
+                })
+                .unwrap();
+            let entry = queue
+                .entries
+                .iter_mut()
+                .find(|entry| entry.state == State::Queued)
+                .unwrap();
+            entry.state = State::Running;
+            entry.started = Some(now());
+            (entry.id, entry.job.clone(), PathBuf::from(&entry.log))
+        };
+        info!("Job {} started", id);
+        let result = execute(&shared, id, &job, &log_path);
+
+        let mut queue = lock.lock().unwrap();
+        let entry = queue.get_mut(id).unwrap();
+        entry.finished = Some(now());
+        match result {
+            // 取消请求到达时进程可能已正常结束
+            Ok(status) if status.success() => entry.state = State::Succeeded,
+            _ if entry.cancel => entry.state = State::Cancelled,
+            Ok(status) => {
+                entry.state = State::Failed;
+                entry.exit_code = status.code();
+                entry.error = Some(format!("Exited with {}", status));
+            }
+            Err(e) => {
+                entry.state = State::Failed;
+                entry.error = Some(e.to_string());
+            }
+        }
+        match entry.state {
+            State::Failed => error!(
+                "Job {} failed: {}",
+                id,
+                entry.error.as_deref().unwrap_or("unknown error")
+            ),
+            state => info!("Job {} {:?}", id, state),
+        }
+    }
+}
+
+/// 提交任务, 请求体为JSON对象, 键与命令行长参数同名; 可选的name与dir分别为任务名与工作目录
+fn submit(shared: &Shared, log_dir: &Path, body: &[u8]) -> anyhow::Result<Value> {
+    let mut options = match serde_json::from_slice::<Value>(body)? {
+        Value::Object(options) => options,
+        _ => return Err(anyhow!("Request body must be a JSON object")),
+    };
+    // 参数名中的_与-等价, 见value_to_arg
+    for key in options.keys() {
+        if RESERVED.contains(&key.replace('_', "-").as_str()) {
+            return Err(anyhow!("{} is managed by the daemon", key));
+        }
+    }
+    let name = match options.remove("name") {
+        Some(Value::String(name)) => Some(name),
+        None => None,
+        Some(value) => return Err(anyhow!("Invalid name: {}", value)),
+    };
+    let dir = match options.remove("dir") {
+        Some(Value::String(dir)) => PathBuf::from(dir),
+        None => PathBuf::from("."),
+        Some(value) => return Err(anyhow!("Invalid dir: {}", value)),
+    };
+    let table = serde_json::from_value::<Table>(Value::Object(options))
+        .map_err(|e| anyhow!("Invalid options: {}", e))?;
+
+    let (lock, condvar) = &**shared;
+    let mut queue = lock.lock().unwrap();
+    queue.next_id += 1;
+    let id = queue.next_id;
+    let job = Job::from_table(name.unwrap_or(format!("job-{}", id)), &table, &dir)?;
+    let entry = Entry {
+        id,
+        job,
+        state: State::Queued,
+        progress: None,
+        submitted: now(),
+        started: None,
+        finished: None,
+        exit_code: None,
+        error: None,
+        log: log_dir.join(format!("{}.log", id)).display().to_string(),
+        child: None,
+        cancel: false,
+    };
+    let value = serde_json::to_value(&entry)?;
+    queue.entries.push(entry);
+    condvar.notify_one();
+    info!("Job {} queued", id);
+    return Ok(value);
+}
+
+/// 取消任务: 排队中的任务直接取消, 运行中的任务结束其进程
+fn cancel(shared: &Shared, id: u64) -> anyhow::Result<Value> {
+    let (lock, _) = &**shared;
+    let mut queue = lock.lock().unwrap();
+    let entry = queue.get_mut(id).ok_or(anyhow!("Job {} not found", id))?;
+    match entry.state {
+        State::Queued => {
+            entry.state = State::Cancelled;
+            entry.finished = Some(now());
+        }
+        State::Running => {
+            if let Some(child) = entry.child.as_mut() {
+                // 进程已退出, 等待工作线程记录结果
+                if let Ok(Some(_)) = child.try_wait() {
+                    return Err(anyhow!("Job {} has already finished", id));
+                }
+                stop(child).map_err(|e| anyhow!("Failed to stop job {}: {}", id, e))?;
+            }
+            entry.cancel = true;
+        }
+        _ => return Err(anyhow!("Job {} has already finished", id)),
+    }
+    info!("Job {} cancelled", id);
+    return Ok(serde_json::to_value(&*entry)?);
+}
+
+fn route(
+    shared: &Shared,
+    log_dir: &Path,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> (&'static str, Value) {
+    let error = |status, e: anyhow::Error| (status, json!({ "error": e.to_string() }));
+    let segments = path.trim_matches('/').split('/').collect::<Vec<&str>>();
+    match (method, segments.as_slice()) {
+        ("GET", ["jobs"]) => {
+            let queue = shared.0.lock().unwrap();
+            match serde_json::to_value(&queue.entries) {
+                Ok(value) => ("200 OK", value),
+                Err(e) => error("500 Internal Server Error", e.into()),
+            }
+        }
+        ("POST", ["jobs"]) => match submit(shared, log_dir, body) {
+            Ok(value) => ("201 Created", value),
+            Err(e) => error("400 Bad Request", e),
+        },
+        (method, ["jobs", id]) => {
+            let id = match id.parse::<u64>() {
+                Ok(id) => id,
+                Err(e) => return error("400 Bad Request", anyhow!("Invalid job id: {}", e)),
+            };
+            match method {
+                "GET" => {
+                    let mut queue = shared.0.lock().unwrap();
+                    match queue.get_mut(id).map(|entry| serde_json::to_value(&*entry)) {
+                        Some(Ok(value)) => ("200 OK", value),
+                        Some(Err(e)) => error("500 Internal Server Error", e.into()),
+                        None => error("404 Not Found", anyhow!("Job {} not found", id)),
+                    }
+                }
+                "DELETE" => match cancel(shared, id) {
+                    Ok(value) => ("200 OK", value),
+                    Err(e) => error("409 Conflict", e),
+                },
+                _ => error("405 Method Not Allowed", anyhow!("Unsupported method")),
+            }
+        }
+        _ => error("404 Not Found", anyhow!("Unknown path {}", path)),
+    }
+}
+
+fn respond_error(stream: &mut TcpStream, status: &str, message: &str) -> anyhow::Result<()> {
+    respond(
+        stream,
+        status,
+        "application/json",
+        serde_json::to_string(&json!({ "error": message }))?.as_bytes(),
+    )?;
+    return Ok(());
+}
+
+fn handle(shared: &Shared, log_dir: &Path, stream: &mut TcpStream) -> anyhow::Result<()> {
+    let mut request = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    request.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if request.read_line(&mut header)? <= 2 {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = match value.trim().parse::<usize>() {
+                    Ok(length) => length,
+                    Err(_) => {
+                        return respond_error(stream, "400 Bad Request", "Invalid Content-Length")
+                    }
+                };
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return respond_error(stream, "413 Payload Too Large", "Request body too large");
+    }
+    let mut body = vec![0; content_length];
+    request.read_exact(&mut body)?;
+    debug!("Request: {}", request_line.trim());
+
+    let mut parts = request_line.split_whitespace();
+    let (status, value) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => {
+            let path = target.split_once('?').map_or(target, |(path, _)| path);
+            route(shared, log_dir, method, path, &body)
+        }
+        _ => ("400 Bad Request", json!({ "error": "Invalid request" })),
+    };
+    respond(
+        stream,
+        status,
+        "application/json",
+        serde_json::to_string(&value)?.as_bytes(),
+    )?;
+    return Ok(());
+}
+
+/// 启动本地HTTP任务接口, 任务在进程内排队, 逐个交由子进程压制
+///
+/// - `POST /jobs` 提交任务
+/// - `GET /jobs` 列出全部任务及历史
+/// - `GET /jobs/<id>` 查询状态与进度
+/// - `DELETE /jobs/<id>` 取消任务
+pub fn run(arg: &DaemonArg) -> anyhow::Result<()> {
+    std::fs::create_dir_all(&arg.log_dir)
+        .map_err(|e| anyhow!("Failed to create {}: {}", arg.log_dir, e))?;
+    let log_dir = std::fs::canonicalize(&arg.log_dir)
+        .map_err(|e| anyhow!("Failed to resolve {}: {}", arg.log_dir, e))?;
+    let shared: Shared = Arc::new((Mutex::new(Queue::default()), Condvar::new()));
+    for _ in 0..arg.jobs.max(1) {
+        let shared = shared.clone();
+        std::thread::spawn(move || worker(shared));
+    }
+
+    let listener = TcpListener::bind(&arg.listen)
+        .map_err(|e| anyhow!("Failed to listen on {}: {}", arg.listen, e))?;
+    info!("Job API started at http://{}/jobs", arg.listen);
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        // 每个连接在单独的线程中处理, 慢速客户端不影响其他请求
+        let shared = shared.clone();
+        let log_dir = log_dir.clone();
+        std::thread::spawn(move || {
+            let result = stream
+                .set_read_timeout(Some(IO_TIMEOUT))
+                .and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT)))
+                .map_err(anyhow::Error::from)
+                .and_then(|_| handle(&shared, &log_dir, &mut stream));
+            if let Err(e) = result {
+                warn!("Failed to handle request: {}", e);
+            }
+        });
+    }
+    return Ok(());
+}
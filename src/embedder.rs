@@ -1,5 +1,5 @@
-use ffmpeg_next::frame::Video;
-use log::info;
+use ffmpeg_next::{format::Pixel, frame::Video};
+use log::debug;
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 
 use crate::render::RenderData;
@@ -9,6 +9,8 @@ pub struct SubtitleEmbedder<'a> {
     // begin from 0
     // next_first_flap: u64,
     buf: Vec<Video>,
+    // 缓冲区中每帧在原视频中的帧号, 从1开始
+    flaps: Vec<usize>,
     buffer_size: usize,
     top_offset: u32,
     bottom_offset: u32,
@@ -33,25 +35,28 @@ impl<'a> SubtitleEmbedder<'a> {
                 v.reserve(buffer_size);
                 v
             },
+            flaps: Vec::with_capacity(buffer_size),
             buffer_size,
             top_offset,
             bottom_offset,
         }
     }
-    pub fn send_frame(&mut self, frame: Video) -> anyhow::Result<bool> {
+    // flap starts from 1
+    pub fn send_frame(&mut self, frame: Video, flap: usize) -> anyhow::Result<bool> {
         if self.buf.len() >= self.buffer_size {
             return Err(anyhow!("Buffer is full!"));
         }
         self.buf.push(frame);
+        self.flaps.push(flap);
         if self.buf.len() == self.buffer_size {
             return Ok(true);
         } else {
             return Ok(false);
         }
     }
-    // frame starts from 1
+    // frame starts from 1, 为输出视频中的帧号
     pub fn embed(&mut self, frame_begin: i64, frame_end: i64) -> anyhow::Result<()> {
-        info!("Embedding frame [{}, {}]", frame_begin, frame_end);
+        debug!("Embedding frame [{}, {}]", frame_begin, frame_end);
         if self.buf.len() as i64 != frame_end - frame_begin + 1 {
             return Err(anyhow!(
                 "Invalid frame range! Expected {}, received {} to {}",
@@ -62,60 +67,62 @@ impl<'a> SubtitleEmbedder<'a> {
         }
         let frame_ref = &mut self.buf[..];
         // info!("self renderdata length = {}", self.render_data.len());
-        let renderdata_ref =
-            &self.render_data[(frame_begin - 1) as usize..=(frame_end - 1) as usize];
+        let renderdata_ref = self.render_data;
         let top_offset = self.top_offset;
         let bottom_offset = self.bottom_offset;
         // info!("top: {}, bottom: {}", top_offset, bottom_offset);
         frame_ref
             .into_par_iter()
-            .zip(renderdata_ref)
-            .for_each(move |(frame, render_data)| {
+            .zip(&self.flaps[..])
+            .for_each(move |(frame, flap)| {
+                let render_data = match renderdata_ref.get(*flap - 1) {
+                    Some(v) => v,
+                    None => return,
+                };
                 unsafe {
                     let ptr_ref = *frame.as_ptr();
                     let frame_height = ptr_ref.height;
                     let frame_width = ptr_ref.width;
-                    let data_ref = &mut frame.data_mut(0);
+                    let high_depth = frame.format() == Pixel::RGB48LE;
+                    let data_ref: &mut [u8] = frame.data_mut(0);
                     // info!("Main height: {}, width: {}", frame_height, frame_width);
-                    if let Some(major) = &render_data.major {
-                        let ExtractResult {
-                            width,
-                            height,
-                            data,
-                            linesize,
-                        } = extract_things(&*major.image);
-                        // info!("major height: {}, width: {}", height, width);
-                        raw_embed(
-                            data_ref,
-                            frame_width,
-                            frame_height,
-                            ptr_ref.linesize[0],
-                            data,
-                            width,
-                            height,
-                            linesize,
-                            Offset::MajorBottom(bottom_offset),
-                        );
-                    }
-                    if let Some(minor) = &render_data.minor {
-                        let ExtractResult {
-                            width,
-                            height,
-                            data,
-                            linesize,
-                        } = extract_things(&*minor.image);
-                        // info!("minor height: {}, width: {}", height, width);
-                        raw_embed(
-                            data_ref,
-                            frame_width,
-                            frame_height,
-                            ptr_ref.linesize[0],
-                            data,
-                            width,
-                            height,
-                            linesize,
-                            Offset::MinorTop(top_offset),
-                        );
+                    for (subtitle, offset) in [
+                        (&render_data.major, Offset::MajorBottom(bottom_offset)),
+                        (&render_data.minor, Offset::MinorTop(top_offset)),
+                    ] {
+                        if let Some(subtitle) = subtitle {
+                            let ExtractResult {
+                                width,
+                                height,
+                                data,
+                                linesize,
+                            } = extract_things(&*subtitle.image);
+                            if high_depth {
+                                raw_embed(
+                                    as_samples_mut::<u16>(&mut *data_ref),
+                                    frame_width,
+                                    frame_height,
+                                    ptr_ref.linesize[0] / 2,
+                                    as_samples::<u16>(data),
+                                    width,
+                                    height,
+                                    linesize / 2,
+                                    offset,
+                                );
+                            } else {
+                                raw_embed(
+                                    &mut *data_ref,
+                                    frame_width,
+                                    frame_height,
+                                    ptr_ref.linesize[0],
+                                    data,
+                                    width,
+                                    height,
+                                    linesize,
+                                    offset,
+                                );
+                            }
+                        }
                     }
 
                     // let lurow = if render_data.major
@@ -126,6 +133,7 @@ impl<'a> SubtitleEmbedder<'a> {
     }
     pub fn finish(&mut self) {
         self.buf.clear();
+        self.flaps.clear();
         self.buf.reserve(self.buffer_size);
     }
 }
@@ -135,23 +143,81 @@ pub(crate) enum Offset {
     MinorTop(u32),
 }
 
+/// 合成时的单个颜色分量, RGB24为u8, RGB48LE为u16
+pub(crate) trait Sample: Copy {
+    fn is_zero(self) -> bool;
+    fn blend(self, other: Self) -> Self;
+}
+
+impl Sample for u8 {
+    #[inline]
+    fn is_zero(self) -> bool {
+        self == 0
+    }
+    #[inline]
+    fn blend(self, other: Self) -> Self {
+        ((self as u16 + other as u16) / 2) as u8
+    }
+}
+
+impl Sample for u16 {
+    #[inline]
+    fn is_zero(self) -> bool {
+        self == 0
+    }
+    #[inline]
+    fn blend(self, other: Self) -> Self {
+        ((self as u32 + other as u32) / 2) as u16
+    }
+}
+
+#[inline]
+unsafe fn as_samples<T: Sample>(data: &[u8]) -> &[T] {
+    std::slice::from_raw_parts(
+        data.as_ptr() as *const T,
+        data.len() / std::mem::size_of::<T>(),
+    )
+}
+
+#[inline]
+unsafe fn as_samples_mut<T: Sample>(data: &mut [u8]) -> &mut [T] {
+    std::slice::from_raw_parts_mut(
+        data.as_mut_ptr() as *mut T,
+        data.len() / std::mem::size_of::<T>(),
+    )
+}
+
+/// 字幕图片左上角在视频中的位置 (行, 列)
 #[inline]
-pub(crate) fn raw_embed(
-    src_img: &mut [u8],
+pub(crate) fn placement(
+    img_colc: i32,
+    img_rowc: i32,
+    colc: i32,
+    rowc: i32,
+    offset: &Offset,
+) -> (i32, i32) {
+    let lurow = match offset {
+        Offset::MajorBottom(v) => img_rowc - *v as i32 - rowc,
+        Offset::MinorTop(v) => *v as i32,
+    };
+    let lucol = (img_colc - colc) / 2;
+    return (lurow, lucol);
+}
+
+// linesize均以分量个数为单位
+#[inline]
+pub(crate) fn raw_embed<T: Sample>(
+    src_img: &mut [T],
     img_colc: i32,
     img_rowc: i32,
     img_linesize: i32,
-    subtitle_img: &[u8],
+    subtitle_img: &[T],
     colc: i32,
     rowc: i32,
     linesize: i32,
     offset: Offset,
 ) {
-    let lurow = match offset {
-        Offset::MajorBottom(v) => img_rowc - v as i32 - rowc,
-        Offset::MinorTop(v) => v as i32,
-    };
-    let lucol = (img_colc - colc) / 2;
+    let (lurow, lucol) = placement(img_colc, img_rowc, colc, rowc, &offset);
     /*
     i行j列像素(i,j) (从1开始)
     */
@@ -166,15 +232,13 @@ pub(crate) fn raw_embed(
             let img_base = ((r + lurow) * img_linesize + (c + lucol) * 3) as usize;
             let subtitle_base = (r * linesize + 3 * c) as usize;
             // 背景色半透明
-            if (subtitle_img[subtitle_base]
-                | subtitle_img[subtitle_base + 1]
-                | subtitle_img[subtitle_base + 2])
-                == 0
+            if subtitle_img[subtitle_base].is_zero()
+                && subtitle_img[subtitle_base + 1].is_zero()
+                && subtitle_img[subtitle_base + 2].is_zero()
             {
                 for x in 0..3 {
-                    src_img[img_base + x] = ((subtitle_img[subtitle_base + x] as u16
-                        + src_img[img_base + x] as u16)
-                        / 2) as u8;
+                    src_img[img_base + x] =
+                        subtitle_img[subtitle_base + x].blend(src_img[img_base + x]);
                 }
             } else {
                 //非背景色，不透明
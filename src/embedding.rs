@@ -0,0 +1,177 @@
+use std::ffi::OsString;
+
+use anyhow::anyhow;
+use clap::Parser;
+
+use crate::{
+    cancel::CancelToken,
+    cmdline::InputArg,
+    pipeline::{self, Hooks},
+    progress::{ProgressMode, ProgressUpdate},
+    subtitle::{Subtitle, SubtitleSource},
+};
+
+/// 一次字幕压制, 由 [`EmbeddingBuilder`] 构造
+///
+/// ```no_run
+/// use villagers_embedding_tool::Embedding;
+///
+/// Embedding::builder()
+///     .input("lec.mp4")
+///     .subtitle_dir("subtitle-images")
+///     .output("lec-embedded.mp4")
+///     .bottom_offset(60)
+///     .on_progress(|update| println!("{}/{}", update.frames_done, update.total_frames))
+///     .build()?
+///     .run()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Embedding {
+    arg: InputArg,
+    source: Option<Box<dyn SubtitleSource>>,
+    hooks: Hooks,
+}
+
+impl Embedding {
+    pub fn builder() -> EmbeddingBuilder {
+        EmbeddingBuilder::new()
+    }
+    /// 运行压制, 阻塞至完成; 通过取消令牌停止时返回错误, 输出为截断但可播放的文件
+    pub fn run(self) -> anyhow::Result<()> {
+        ffmpeg_next::init().map_err(|e| anyhow!("Failed to initialize ffmpeg: {}", e))?;
+        return pipeline::run(&self.arg, self.source, self.hooks);
+    }
+}
+
+/// 压制参数的构造器, 未设置的参数与命令行的默认值相同
+pub struct EmbeddingBuilder {
+    arg: InputArg,
+    subtitles: Vec<Subtitle>,
+    source: Option<Box<dyn SubtitleSource>>,
+    hooks: Hooks,
+}
+
+impl EmbeddingBuilder {
+    pub fn new() -> Self {
+        Self::with_arg(InputArg::parse_from([env!("CARGO_PKG_NAME")]))
+    }
+    /// 由命令行形式的参数构造, 如 `["--output=lec-embedded.mp4", "--bottom-offset=60"]`
+    pub fn from_args<I, T>(args: I) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        let arg = InputArg::try_parse_from(
+            std::iter::once(OsString::from(env!("CARGO_PKG_NAME")))
+                .chain(args.into_iter().map(Into::into)),
+        )
+        .map_err(|e| anyhow!("Invalid arguments: {}", e))?;
+        return Ok(Self::with_arg(arg));
+    }
+    fn with_arg(mut arg: InputArg) -> Self {
+        // 作为库调用时不在终端中绘制进度条
+        if arg.progress == ProgressMode::Auto {
+            arg.progress = ProgressMode::Log;
+        }
+        Self {
+            arg,
+            subtitles: vec![],
+            source: None,
+            hooks: Hooks::default(),
+        }
+    }
+    /// 输入视频, 多次调用时按顺序拼接
+    pub fn input(mut self, path: impl Into<String>) -> Self {
+        self.arg.input.push(path.into());
+        self
+    }
+    pub fn output(mut self, path: impl Into<String>) -> Self {
+        self.arg.output = path.into();
+        self
+    }
+    /// 字幕图片所在的文件夹, 或包含字幕图片的压缩包
+    pub fn subtitle_dir(mut self, path: impl Into<String>) -> Self {
+        self.arg.subtitle_files = path.into();
+        self
+    }
+    /// 添加已载入的字幕; 添加过字幕时不再读取字幕文件夹
+    pub fn subtitle(mut self, subtitle: Subtitle) -> Self {
+        self.subtitles.push(subtitle);
+        self
+    }
+    /// 从自定义的来源载入字幕, 代替字幕文件夹
+    pub fn subtitle_source(mut self, source: impl SubtitleSource + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+    /// 主字幕底边距离视频底部的距离
+    pub fn bottom_offset(mut self, offset: u32) -> Self {
+        self.arg.bottom_offset = offset;
+        self
+    }
+    /// 副字幕顶边距离视频顶部的距离
+    pub fn top_offset(mut self, offset: u32) -> Self {
+        self.arg.top_offset = offset;
+        self
+    }
+    /// libx264编码预设
+    pub fn preset(mut self, preset: impl Into<String>) -> Self {
+        self.arg.x264_preset = preset.into();
+        self
+    }
+    /// 视频输出码率, 默认使用解码器码率
+    pub fn bitrate(mut self, bitrate: usize) -> Self {
+        self.arg.bitrate = Some(bitrate);
+        self
+    }
+    /// 预览版: 缩小到指定高度并使用最快的编码预设
+    pub fn proxy(mut self, height: u32) -> Self {
+        self.arg.proxy = Some(height);
+        self
+    }
+    pub fn chunk_size(mut self, chunk_size: u32) -> Self {
+        self.arg.chunk_size = chunk_size;
+        self
+    }
+    pub fn worker_count(mut self, worker_count: u32) -> Self {
+        self.arg.worker_count = worker_count;
+        self
+    }
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&ProgressUpdate) + Send + 'static,
+    {
+        self.hooks.on_progress = Some(Box::new(callback));
+        self
+    }
+    /// 用于从其他线程取消压制的令牌
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.hooks.cancel = token;
+        self
+    }
+    pub fn build(self) -> anyhow::Result<Embedding> {
+        if self.arg.input.is_empty() {
+            return Err(anyhow!("No input file specified"));
+        }
+        let source = match (self.source, self.subtitles.is_empty()) {
+            (source, true) => source,
+            (None, false) => Some(Box::new(self.subtitles) as Box<dyn SubtitleSource>),
+            (Some(_), false) => {
+                return Err(anyhow!(
+                    "Subtitles can't be added when a subtitle source is set"
+                ))
+            }
+        };
+        return Ok(Embedding {
+            arg: self.arg,
+            source,
+            hooks: self.hooks,
+        });
+    }
+}
+
+impl Default for EmbeddingBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
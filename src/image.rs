@@ -2,12 +2,13 @@ use anyhow::anyhow;
 use ffmpeg_next::media::Type;
 use ffmpeg_next::Error;
 use ffmpeg_next::{
-    codec,
+    codec, decoder, encoder,
     format::{input, Pixel},
     frame::Video,
     software::scaling::Flags,
+    Packet,
 };
-use ffmpeg_sys_next::avcodec_parameters_to_context;
+use ffmpeg_sys_next::{avcodec_alloc_context3, avcodec_parameters_to_context};
 use std::path::Path;
 pub fn read_image(path: &Path) -> anyhow::Result<Video> {
     let mut ictx = input(&path).map_err(|e| anyhow!("Failed to open file: {}", e))?;
@@ -51,3 +52,53 @@ pub fn read_image(path: &Path) -> anyhow::Result<Video> {
     }
     return Err(anyhow!("Failed to find image!"));
 }
+
+/// 解码内存中的PNG图片为RGB24帧
+pub fn decode_png(data: &[u8]) -> anyhow::Result<Video> {
+    let png = decoder::find(codec::Id::PNG).ok_or(anyhow!("Missing png decoder!"))?;
+    let mut png_decoder = decoder::new().open_as(png)?.video()?;
+    png_decoder.send_packet(&Packet::copy(data))?;
+    png_decoder.send_eof()?;
+    let mut decoded = Video::empty();
+    png_decoder
+        .receive_frame(&mut decoded)
+        .map_err(|e| anyhow!("Failed to decode png: {}", e))?;
+    let mut scaler = ffmpeg_next::software::scaling::Context::get(
+        decoded.format(),
+        decoded.width(),
+        decoded.height(),
+        Pixel::RGB24,
+        decoded.width(),
+        decoded.height(),
+        Flags::BILINEAR,
+    )?;
+    let mut rgb_frame = Video::empty();
+    scaler.run(&decoded, &mut rgb_frame)?;
+    return Ok(rgb_frame);
+}
+
+/// 将RGB24帧编码为PNG图片
+pub fn encode_png(frame: &Video) -> anyhow::Result<Vec<u8>> {
+    let png = encoder::find(codec::Id::PNG).ok_or(anyhow!("Missing png encoder!"))?;
+    let context =
+        unsafe { codec::context::Context::wrap(avcodec_alloc_context3(png.as_ptr()), None) };
+    let mut png_encoder = context.encoder().video()?;
+    png_encoder.set_width(frame.width());
+    png_encoder.set_height(frame.height());
+    png_encoder.set_format(frame.format());
+    png_encoder.set_time_base((1, 1));
+    let mut png_encoder = png_encoder.open_as(png)?;
+    png_encoder.send_frame(frame)?;
+    png_encoder.send_eof()?;
+    let mut packet = Packet::empty();
+    png_encoder
+        .receive_packet(&mut packet)
+        .map_err(|e| anyhow!("Failed to encode png: {}", e))?;
+    return Ok(packet.data().unwrap_or(&[]).to_vec());
+}
+
+pub fn save_png(frame: &Video, path: &Path) -> anyhow::Result<()> {
+    std::fs::write(path, encode_png(frame)?)
+        .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+    return Ok(());
+}
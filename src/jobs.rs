@@ -0,0 +1,125 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::Instant,
+};
+
+use anyhow::anyhow;
+use serde::Serialize;
+use toml::value::Table;
+
+use crate::config::value_to_arg;
+
+/// 一个压制任务: 在工作目录下以给定参数运行一次压制
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub name: String,
+    pub args: Vec<String>,
+    pub dir: PathBuf,
+}
+
+impl Job {
+    /// 由参数表构造任务, 表中的键与命令行长参数同名
+    pub fn from_table(name: String, table: &Table, dir: &Path) -> anyhow::Result<Self> {
+        let mut args = vec![];
+        for (key, value) in table.iter() {
+            args.extend(value_to_arg(key, value)?);
+        }
+        return Ok(Self {
+            name,
+            args,
+            dir: dir.to_path_buf(),
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Ok,
+    Failed,
+}
+
+/// 单个任务的运行结果
+#[derive(Debug, Clone, Serialize)]
+pub struct JobResult {
+    pub name: String,
+    pub status: JobStatus,
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    pub elapsed: f64,
+    pub log: String,
+}
+
+/// 在子进程中运行任务, 标准输出与标准错误写入日志文件; 任务失败不影响调用方
+pub fn run_job(job: &Job, log_path: &Path) -> JobResult {
+    let start = Instant::now();
+    let result = spawn_job(job, log_path);
+    let (status, exit_code, error) = match result {
+        Ok(status) if status.success() => (JobStatus::Ok, status.code(), None),
+        Ok(status) => (
+            JobStatus::Failed,
+            status.code(),
+            Some(format!("Exited with {}", status)),
+        ),
+        Err(e) => (JobStatus::Failed, None, Some(e.to_string())),
+    };
+    return JobResult {
+        name: job.name.clone(),
+        status,
+        exit_code,
+        error,
+        elapsed: start.elapsed().as_secs_f64(),
+        log: log_path.display().to_string(),
+    };
+}
+
+fn job_command(job: &Job) -> anyhow::Result<Command> {
+    let exe = std::env::current_exe().map_err(|e| anyhow!("Failed to locate executable: {}", e))?;
+    let mut command = Command::new(exe);
+    command
+        .args(&job.args)
+        .current_dir(&job.dir)
+        .stdin(Stdio::null());
+    // 子进程只使用任务中给出的参数, 不自动读取工作目录下的配置文件
+    if !job
+        .args
+        .iter()
+        .any(|arg| arg.starts_with("--config=") || arg == "--no-config")
+    {
+        command.arg("--no-config");
+    }
+    return Ok(command);
+}
+
+fn spawn_job(job: &Job, log_path: &Path) -> anyhow::Result<std::process::ExitStatus> {
+    let log = File::create(log_path)
+        .map_err(|e| anyhow!("Failed to create log {}: {}", log_path.display(), e))?;
+    let mut command = job_command(job)?;
+    // 日志文件中不需要进度条
+    if !job.args.iter().any(|arg| arg.starts_with("--progress=")) {
+        command.arg("--progress=log");
+    }
+    let status = command
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .status()
+        .map_err(|e| anyhow!("Failed to start job {}: {}", job.name, e))?;
+    return Ok(status);
+}
+
+/// 启动任务子进程, 标准输出为每行一个的JSON进度事件, 日志写入日志文件
+///
+/// 任务参数中不能再指定 `--progress`
+pub fn start_job(job: &Job, log_path: &Path) -> anyhow::Result<Child> {
+    let log = File::create(log_path)
+        .map_err(|e| anyhow!("Failed to create log {}: {}", log_path.display(), e))?;
+    let child = job_command(job)?
+        .arg("--progress=json")
+        .stdout(Stdio::piped())
+        .stderr(log)
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start job {}: {}", job.name, e))?;
+    return Ok(child);
+}
@@ -0,0 +1,46 @@
+//! 村民压制工具: 将字幕图片压制进视频
+//!
+//! 命令行程序之外, 可通过 [`Embedding`] 在其他程序中调用压制流程;
+//! 启用`capi`特性时另提供C接口, 见capi模块
+
+pub mod archive;
+mod audio;
+mod batch;
+pub mod cancel;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[doc(hidden)]
+pub mod cli;
+mod cmdline;
+mod color;
+mod concat;
+mod config;
+mod contact_sheet;
+mod daemon;
+pub mod embedder;
+pub mod embedding;
+mod image;
+mod jobs;
+mod mapping;
+mod metadata;
+mod pipeline;
+mod preview;
+pub mod progress;
+mod proxy;
+mod reader;
+pub mod render;
+mod resume;
+mod serve;
+pub mod subtitle;
+mod summary;
+mod trim;
+mod verify;
+mod watch;
+
+pub use archive::ArchiveSource;
+pub use cancel::CancelToken;
+pub use embedder::SubtitleEmbedder;
+pub use embedding::{Embedding, EmbeddingBuilder};
+pub use progress::ProgressUpdate;
+pub use render::{init_render_data, RenderData};
+pub use subtitle::{load_subtitles, DirSource, MemorySource, Subtitle, SubtitleSource};
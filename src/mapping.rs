@@ -0,0 +1,275 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use ffmpeg_next::{format::context::Input, media::Type};
+use log::{debug, info, warn};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Matcher {
+    // 输入文件中的流编号
+    Index(usize),
+    // 某类型的全部流, 或该类型中的第n个
+    Kind(Type, Option<usize>),
+    // 按metadata中的language标签选择, 可限定类型
+    Language(Option<Type>, String),
+}
+
+/// `--map`参数, 形如 `1`, `a`, `a:0`, `s:lang=chi`, `lang=jpn`, 以`-`开头表示丢弃
+///
+/// 兼容ffmpeg写法, 可带输入文件编号`0:`, 如 `0:a:0`, `-0:s`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamMap {
+    pub negative: bool,
+    pub matcher: Matcher,
+}
+
+fn parse_kind(s: &str) -> Result<Type, String> {
+    match s {
+        "v" => Ok(Type::Video),
+        "a" => Ok(Type::Audio),
+        "s" => Ok(Type::Subtitle),
+        "d" => Ok(Type::Data),
+        "t" => Ok(Type::Attachment),
+        _ => Err(format!("Invalid stream type: {}", s)),
+    }
+}
+
+impl FromStr for StreamMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, spec) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let mut parts = spec.split(':').collect::<Vec<&str>>();
+        if parts.len() > 1 && parts[0].parse::<usize>().is_ok() {
+            if parts[0] != "0" {
+                return Err(format!("Only input 0 can be mapped: {}", s));
+            }
+            parts.remove(0);
+        }
+        let matcher = match parts[..] {
+            [v] if v.starts_with("lang=") => Matcher::Language(None, v[5..].to_string()),
+            [v] => match v.parse::<usize>() {
+                Ok(index) => Matcher::Index(index),
+                Err(_) => Matcher::Kind(parse_kind(v)?, None),
+            },
+            [kind, v] if v.starts_with("lang=") => {
+                Matcher::Language(Some(parse_kind(kind)?), v[5..].to_string())
+            }
+            [kind, v] => Matcher::Kind(
+                parse_kind(kind)?,
+                Some(
+                    v.parse::<usize>()
+                        .map_err(|e| format!("Invalid stream number {}: {}", v, e))?,
+                ),
+            ),
+            _ => return Err(format!("Invalid stream map: {}", s)),
+        };
+        return Ok(Self { negative, matcher });
+    }
+}
+
+struct StreamInfo {
+    index: usize,
+    kind: Type,
+    // 在同类型流中的序号
+    kind_index: usize,
+    language: Option<String>,
+}
+
+impl StreamMap {
+    fn matches(&self, stream: &StreamInfo) -> bool {
+        match &self.matcher {
+            Matcher::Index(index) => stream.index == *index,
+            Matcher::Kind(kind, None) => stream.kind == *kind,
+            Matcher::Kind(kind, Some(n)) => stream.kind == *kind && stream.kind_index == *n,
+            Matcher::Language(kind, language) => {
+                kind.map_or(true, |k| k == stream.kind)
+                    && stream.language.as_deref() == Some(language.as_str())
+            }
+        }
+    }
+}
+
+/// 按`--map`参数选出需要输出的流, 返回值为按输出顺序排列的输入流编号
+///
+/// 没有任何正向选择时默认保留视频, 音频和字幕流, 丢弃数据流与附件
+pub fn select_streams(
+    input_ctx: &Input,
+    maps: &[StreamMap],
+    video_stream_index: usize,
+) -> anyhow::Result<Vec<usize>> {
+    let mut streams = Vec::<StreamInfo>::new();
+    for stream in input_ctx.streams() {
+        let kind = stream.codec().medium();
+        let kind_index = streams.iter().filter(|s| s.kind == kind).count();
+        streams.push(StreamInfo {
+            index: stream.index(),
+            kind,
+            kind_index,
+            language: stream.metadata().get("language").map(|v| v.to_string()),
+        });
+    }
+    return select(&streams, maps, video_stream_index);
+}
+
+fn select(
+    streams: &[StreamInfo],
+    maps: &[StreamMap],
+    video_stream_index: usize,
+) -> anyhow::Result<Vec<usize>> {
+    let mut selected = vec![];
+    if maps.iter().all(|m| m.negative) {
+        for stream in streams.iter() {
+            match stream.kind {
+                Type::Video | Type::Audio | Type::Subtitle => selected.push(stream.index),
+                _ => info!(
+                    "Dropping stream {} of type {:?}, use --map to keep it",
+                    stream.index, stream.kind
+                ),
+            }
+        }
+    }
+    for map in maps.iter() {
+        let matched = streams
+            .iter()
+            .filter(|s| map.matches(s))
+            .map(|s| s.index)
+            .collect::<Vec<usize>>();
+        // 丢弃不存在的流不影响输出, 可在不同文件间复用同一组参数
+        if matched.is_empty() && map.negative {
+            warn!("Stream map {:?} matches no stream, ignoring", map);
+            continue;
+        }
+        if matched.is_empty() {
+            return Err(anyhow!("Stream map {:?} matches no stream", map));
+        }
+        if map.negative {
+            selected.retain(|idx| !matched.contains(idx));
+        } else {
+            for idx in matched {
+                if !selected.contains(&idx) {
+                    selected.push(idx);
+                }
+            }
+        }
+    }
+    if !selected.contains(&video_stream_index) {
+        return Err(anyhow!(
+            "Video stream {} to embed subtitles into is not selected",
+            video_stream_index
+        ));
+    }
+    debug!("Selected streams: {:?}", selected);
+    return Ok(selected);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(s: &str) -> StreamMap {
+        s.parse().unwrap()
+    }
+
+    fn stream(index: usize, kind: Type, kind_index: usize, language: Option<&str>) -> StreamInfo {
+        StreamInfo {
+            index,
+            kind,
+            kind_index,
+            language: language.map(|l| l.to_string()),
+        }
+    }
+
+    // 视频, 两条音频 (jpn, chi), 一条字幕 (chi), 一个附件
+    fn streams() -> Vec<StreamInfo> {
+        vec![
+            stream(0, Type::Video, 0, None),
+            stream(1, Type::Audio, 0, Some("jpn")),
+            stream(2, Type::Audio, 1, Some("chi")),
+            stream(3, Type::Subtitle, 0, Some("chi")),
+            stream(4, Type::Attachment, 0, None),
+        ]
+    }
+
+    #[test]
+    fn parses_maps() {
+        assert_eq!(
+            map("1"),
+            StreamMap {
+                negative: false,
+                matcher: Matcher::Index(1)
+            }
+        );
+        assert_eq!(map("a").matcher, Matcher::Kind(Type::Audio, None));
+        assert_eq!(map("a:1").matcher, Matcher::Kind(Type::Audio, Some(1)));
+        assert_eq!(
+            map("lang=jpn").matcher,
+            Matcher::Language(None, "jpn".to_string())
+        );
+        assert_eq!(
+            map("s:lang=chi").matcher,
+            Matcher::Language(Some(Type::Subtitle), "chi".to_string())
+        );
+        let negative = map("-s");
+        assert!(negative.negative);
+        assert_eq!(negative.matcher, Matcher::Kind(Type::Subtitle, None));
+    }
+
+    #[test]
+    fn parses_input_prefix() {
+        assert_eq!(map("0:1").matcher, Matcher::Index(1));
+        assert_eq!(map("0:a:0").matcher, Matcher::Kind(Type::Audio, Some(0)));
+        assert_eq!(map("-0:s"), map("-s"));
+        assert!("1:a".parse::<StreamMap>().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_maps() {
+        assert!("x".parse::<StreamMap>().is_err());
+        assert!("a:x".parse::<StreamMap>().is_err());
+        assert!("a:0:1".parse::<StreamMap>().is_err());
+        assert!("".parse::<StreamMap>().is_err());
+    }
+
+    #[test]
+    fn selects_default_streams() {
+        assert_eq!(select(&streams(), &[], 0).unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(
+            select(&streams(), &[map("-a:0")], 0).unwrap(),
+            vec![0, 2, 3]
+        );
+    }
+
+    #[test]
+    fn selects_in_map_order() {
+        assert_eq!(
+            select(&streams(), &[map("v"), map("lang=chi"), map("t")], 0).unwrap(),
+            vec![0, 2, 3, 4]
+        );
+        assert_eq!(
+            select(&streams(), &[map("0"), map("a"), map("-lang=jpn")], 0).unwrap(),
+            vec![0, 2]
+        );
+    }
+
+    #[test]
+    fn ignores_unmatched_negative_map() {
+        assert_eq!(
+            select(&streams(), &[map("-d")], 0).unwrap(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(
+            select(&streams(), &[map("v"), map("-0:s:1")], 0).unwrap(),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn rejects_unmatched_positive_map() {
+        assert!(select(&streams(), &[map("v"), map("a:5")], 0).is_err());
+        assert!(select(&streams(), &[map("a")], 0).is_err());
+    }
+}
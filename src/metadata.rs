@@ -0,0 +1,139 @@
+use anyhow::anyhow;
+use ffmpeg_next::{
+    format::context::{Input, Output},
+    Dictionary, Stream, StreamMut,
+};
+use log::{debug, info};
+
+use crate::trim::Trim;
+
+// 记录压制所用字幕的metadata键名
+pub const SUBTITLE_TAG_KEY: &str = "burned_subtitles";
+
+// 描述编码结果的统计标签 (mkvmerge写入的BPS等与编码器名), 流重新编码或被剪辑后不再准确
+const STATISTICS_TAGS: [&str; 8] = [
+    "BPS",
+    "DURATION",
+    "NUMBER_OF_FRAMES",
+    "NUMBER_OF_BYTES",
+    "_STATISTICS_TAGS",
+    "_STATISTICS_WRITING_APP",
+    "_STATISTICS_WRITING_DATE_UTC",
+    "ENCODER",
+];
+
+/// 是否为统计标签, 忽略大小写与语言后缀 (如BPS-eng)
+fn is_statistics_tag(key: &str) -> bool {
+    let name = key.split('-').next().unwrap_or(key);
+    STATISTICS_TAGS
+        .iter()
+        .any(|tag| name.eq_ignore_ascii_case(tag))
+}
+
+/// 复制全局metadata, 可覆盖标题并写入字幕标签
+pub fn copy_global(
+    input_ctx: &Input,
+    output_ctx: &mut Output,
+    title: Option<&str>,
+    subtitle_tag: Option<&str>,
+) {
+    let mut dict = input_ctx.metadata().to_owned();
+    if let Some(title) = title {
+        dict.set("title", title);
+    }
+    if let Some(tag) = subtitle_tag {
+        dict.set(SUBTITLE_TAG_KEY, tag);
+    }
+    output_ctx.set_metadata(dict);
+}
+
+/// 复制章节, 剪辑时开头被剪掉的章节会被丢弃, 其余章节平移到剪辑后的时间轴
+pub fn copy_chapters(
+    input_ctx: &Input,
+    output_ctx: &mut Output,
+    trim: Option<&Trim>,
+) -> anyhow::Result<()> {
+    for chapter in input_ctx.chapters() {
+        let title = chapter.metadata().get("title").unwrap_or("").to_string();
+        let (start, end) = match trim {
+            Some(trim) => match trim.shift_ts(chapter.start(), chapter.time_base()) {
+                Some(start) => (
+                    start,
+                    trim.shift_ts(chapter.end(), chapter.time_base())
+                        .unwrap_or(chapter.end() - (chapter.start() - start)),
+                ),
+                None => {
+                    debug!("Chapter {} is trimmed, dropping", chapter.id());
+                    continue;
+                }
+            },
+            None => (chapter.start(), chapter.end()),
+        };
+        let mut output_chapter = output_ctx
+            .add_chapter(chapter.id(), chapter.time_base(), start, end, &title)
+            .map_err(|e| anyhow!("Failed to add chapter {}: {}", chapter.id(), e))?;
+        for (key, value) in chapter.metadata().iter() {
+            output_chapter.set_metadata(key, value);
+        }
+    }
+    if input_ctx.nb_chapters() > 0 {
+        info!("{} chapters copied.", input_ctx.nb_chapters());
+    }
+    return Ok(());
+}
+
+/// 复制流的metadata与disposition(默认, 强制等标记), 可覆盖语言标签
+///
+/// reencoded为true时 (重新编码或剪辑过的流) 不复制统计标签
+pub fn copy_stream(
+    input_stream: &Stream,
+    output_stream: &mut StreamMut,
+    language: Option<&str>,
+    reencoded: bool,
+) {
+    let mut dict = Dictionary::new();
+    for (key, value) in input_stream.metadata().iter() {
+        if reencoded && is_statistics_tag(key) {
+            debug!(
+                "Dropping statistics tag of stream {}: {}={}",
+                input_stream.index(),
+                key,
+                value
+            );
+            continue;
+        }
+        dict.set(key, value);
+    }
+    if let Some(language) = language {
+        dict.set("language", language);
+    }
+    output_stream.set_metadata(dict);
+    unsafe {
+        (*output_stream.as_mut_ptr()).disposition = input_stream.disposition().bits();
+    }
+    debug!(
+        "Stream {} -> {}: disposition {:?}, language {:?}",
+        input_stream.index(),
+        output_stream.index(),
+        input_stream.disposition(),
+        language.or(input_stream.metadata().get("language"))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_statistics_tags() {
+        assert!(is_statistics_tag("BPS"));
+        assert!(is_statistics_tag("BPS-eng"));
+        assert!(is_statistics_tag("NUMBER_OF_FRAMES-eng"));
+        assert!(is_statistics_tag("DURATION"));
+        assert!(is_statistics_tag("_STATISTICS_WRITING_DATE_UTC-eng"));
+        assert!(is_statistics_tag("encoder"));
+        assert!(!is_statistics_tag("language"));
+        assert!(!is_statistics_tag("title"));
+        assert!(!is_statistics_tag("handler_name"));
+    }
+}
@@ -0,0 +1,974 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use ffmpeg_next::{
+    codec::{self, Context},
+    encoder,
+    format::{
+        context::{Input, Output},
+        input, output, Pixel,
+    },
+    frame::Video,
+    software::scaling::Flags,
+    threading::Config,
+    Codec, Dictionary, Error, Packet, Rational, Rescale,
+};
+use ffmpeg_sys_next::{
+    av_frame_get_buffer, avcodec_alloc_context3, avcodec_parameters_from_context,
+    avcodec_parameters_to_context, AVRational,
+};
+use log::{debug, info, warn};
+use rayon::ThreadPoolBuilder;
+
+use crate::{
+    audio::{AudioCodec, AudioSettings, AudioTranscoder},
+    cancel::CancelToken,
+    cmdline::{lookup, InputArg},
+    color::{bit_depth, map_subtitles, ColorMapping, HdrMetadata},
+    concat::{
+        check_streams, input_duration, input_origin, open_video_decoder, read_input_list,
+        PartOffset,
+    },
+    embedder::SubtitleEmbedder,
+    mapping::select_streams,
+    metadata::{copy_chapters, copy_global, copy_stream},
+    progress::{Progress, ProgressCallback, Stage},
+    proxy::{scale_subtitles, Proxy, PROXY_PRESET},
+    render::{init_render_data, RenderData},
+    resume::{encoding_settings, JobKey, Segments},
+    subtitle::{load_source, load_subtitles_counted, SubtitleSource},
+    summary::{
+        EncoderSummary, FrameSummary, InputSummary, OutputSummary, StageSummary, Summary,
+        SubtitleSummary, VideoSummary,
+    },
+    trim::Trim,
+};
+
+/// 压制过程中与调用方交互的接口
+#[derive(Default)]
+pub struct Hooks {
+    pub on_progress: Option<ProgressCallback>,
+    pub cancel: CancelToken,
+}
+
+/// 按参数压制字幕, 阻塞至完成; 取消时留下截断但可播放的输出并返回错误
+///
+/// 给出source时从中载入字幕, 否则读取字幕文件夹
+pub fn run(
+    arg: &InputArg,
+    source: Option<Box<dyn SubtitleSource>>,
+    mut hooks: Hooks,
+) -> anyhow::Result<()> {
+    let inputs = {
+        let mut inputs = arg.input.clone();
+        if let Some(list) = &arg.input_list {
+            inputs.extend(read_input_list(&PathBuf::from(list))?);
+        }
+        if inputs.is_empty() {
+            return Err(anyhow!("No input file specified"));
+        }
+        inputs
+    };
+    let mut input_ctxs = Vec::<Input>::new();
+    for name in inputs.iter() {
+        input_ctxs.push(
+            input(name).map_err(|e| anyhow!("Failed to open video file {}: {}", name, e))?,
+        );
+    }
+    let input_ctx = &input_ctxs[0];
+    let trim = Trim::new(arg.start, arg.end, &arg.keep, input_origin(input_ctx))?;
+    if arg.resume && (inputs.len() > 1 || trim.is_some()) {
+        return Err(anyhow!(
+            "--resume does not support multiple inputs or trimming"
+        ));
+    }
+    let mut output_ctx =
+        output(&arg.output).map_err(|e| anyhow!("Failed to open output video file: {}", e))?;
+    let input_video = match arg.video_stream {
+        Some(idx) => input_ctx
+            .stream(idx)
+            .filter(|s| s.codec().medium() == ffmpeg_next::media::Type::Video)
+            .ok_or(anyhow!("Stream {} is not a video stream", idx))?,
+        None => input_ctx
+            .streams()
+            .best(ffmpeg_next::media::Type::Video)
+            .ok_or(anyhow!("Failed to find video stream"))?,
+    };
+
+    let video_stream_index = input_video.index();
+    let selected_streams = select_streams(input_ctx, &arg.map, video_stream_index)?;
+    for (name, ctx) in inputs.iter().zip(input_ctxs.iter()).skip(1) {
+        check_streams(input_ctx, ctx, &selected_streams, name)?;
+    }
+    let total_frames: i64 = input_ctxs
+        .iter()
+        .map(|ctx| ctx.stream(video_stream_index).unwrap().frames())
+        .sum();
+    let input_summaries = inputs
+        .iter()
+        .zip(input_ctxs.iter())
+        .map(|(name, ctx)| InputSummary {
+            file: name.clone(),
+            duration: input_duration(ctx),
+            frames: ctx.stream(video_stream_index).unwrap().frames(),
+        })
+        .collect::<Vec<InputSummary>>();
+    // 输入流编号 -> 输出流编号
+    let mut stream_mapping = vec![None; input_ctx.nb_streams() as usize];
+    let mut audio_transcoders = HashMap::<usize, AudioTranscoder>::new();
+    for (output_idx, &idx) in selected_streams.iter().enumerate() {
+        let input_stream = input_ctx.stream(idx).unwrap();
+        stream_mapping[idx] = Some(output_idx);
+        debug!(
+            "Add stream {} -> {}, type {:?}",
+            idx,
+            output_idx,
+            input_stream.codec().medium()
+        );
+        if input_stream.codec().medium() == ffmpeg_next::media::Type::Audio {
+            let settings = AudioSettings {
+                codec: lookup(&arg.audio_codec, idx).unwrap_or(AudioCodec::Copy),
+                bitrate: lookup(&arg.audio_bitrate, idx),
+                loudness: lookup(&arg.loudness, idx),
+            };
+            if settings.codec == AudioCodec::Copy && settings.loudness.is_some() {
+                return Err(anyhow!(
+                    "Loudness normalization of stream {} requires --audio-codec other than copy",
+                    idx
+                ));
+            }
+            if settings.codec != AudioCodec::Copy {
+                let transcoder = AudioTranscoder::new(&input_stream, &mut output_ctx, &settings)
+                    .map_err(|e| anyhow!("Failed to set up audio stream {}: {}", idx, e))?;
+                audio_transcoders.insert(idx, transcoder);
+                continue;
+            }
+        }
+        let codec_id = if idx == video_stream_index {
+            codec::Id::H264
+        } else {
+            input_stream.codec().id()
+        };
+        debug!("Add stream {}, id {}", idx, codec_id.name());
+        let mut output_stream = output_ctx
+            .add_stream(encoder::find(codec_id))
+            .map_err(|e| anyhow!("Failed to add stream: {}", e))?;
+        output_stream.set_parameters(input_stream.parameters());
+        debug!("Stream parameters: {:#?}", unsafe {
+            let v = *input_stream.as_ptr();
+            v
+        });
+        output_stream.set_time_base(input_stream.time_base());
+        unsafe {
+            (*output_stream.parameters().as_mut_ptr()).codec_tag = 0;
+        }
+    }
+    for (output_idx, &idx) in selected_streams.iter().enumerate() {
+        let input_stream = input_ctx.stream(idx).unwrap();
+        let language = lookup(&arg.language, idx);
+        let reencoded =
+            idx == video_stream_index || audio_transcoders.contains_key(&idx) || trim.is_some();
+        copy_stream(
+            &input_stream,
+            &mut output_ctx.stream_mut(output_idx).unwrap(),
+            language.as_deref(),
+            reencoded,
+        );
+    }
+    copy_global(
+        input_ctx,
+        &mut output_ctx,
+        arg.title.as_deref(),
+        arg.subtitle_tag.as_deref(),
+    );
+    copy_chapters(input_ctx, &mut output_ctx, trim.as_ref())?;
+    let output_video_index = stream_mapping[video_stream_index].unwrap();
+    info!(
+        "Video stream index: {}, output index: {}",
+        video_stream_index, output_video_index
+    );
+    let output_video = output_ctx.stream(output_video_index).unwrap();
+
+    info!("Input video codec: {:#?}", input_video.codec().id());
+    info!("Output video codec: {:#?}", output_video.codec().id());
+
+    // avcodec_paramete
+    // let context_decoder = codec::context::Context::from_parameters()?;
+    let context_decoder = {
+        let parameters = input_video.parameters();
+        let mut context = codec::context::Context::new();
+
+        unsafe {
+            match avcodec_parameters_to_context(context.as_mut_ptr(), parameters.as_ptr()) {
+                e if e < 0 => Err(Error::from(e)),
+                _ => Ok(context),
+            }
+        }
+        .map_err(|e| anyhow!("Failed to copy decoder parameters: {}", e))?
+    };
+    // let context_encoder = codec::context::Context::new();
+    let libx264 =
+        codec::encoder::find_by_name("libx264").ok_or(anyhow!("Missing libx264 encoder!"))?;
+
+    unsafe {
+        let v = *libx264.as_ptr();
+        debug!("Codec: {:#?}", v);
+    }
+    let mut decoder = context_decoder.clone().decoder().video()?;
+    // let fps = input_video.avg_frame_rate();
+    // context_encoder.set
+    let avg_fps = input_video.avg_frame_rate();
+    let color_mapping = ColorMapping::new(
+        decoder.format(),
+        decoder.color_transfer_characteristic(),
+        decoder.color_primaries(),
+        arg.reference_white,
+    );
+    let hdr_metadata = HdrMetadata::from_stream(&input_video);
+    let proxy = arg
+        .proxy
+        .map(|height| Proxy::new(decoder.width(), decoder.height(), height));
+    // 合成与编码时的画面尺寸, 预览版模式下为缩小后的尺寸
+    let (frame_width, frame_height) = proxy.map_or((decoder.width(), decoder.height()), |proxy| {
+        (proxy.width, proxy.height)
+    });
+    let output_bitrate = match (arg.bitrate, &proxy) {
+        (Some(bitrate), _) => bitrate,
+        (None, Some(proxy)) => proxy.scale_bitrate(decoder.bit_rate()),
+        (None, None) => decoder.bit_rate(),
+    };
+    {
+        info!(
+            "Video shape (width, height) = ({}, {}), FPS = {}, total frames: {}",
+            decoder.width(),
+            decoder.height(),
+            input_video.avg_frame_rate(),
+            total_frames
+        );
+        info!("Input file: {}", inputs.join(", "));
+        info!("Output file: {}", &arg.output);
+        info!("Chunk size: {}", &arg.chunk_size);
+        info!("Worker count: {}", &arg.worker_count);
+        info!("Bit rate: {} kb/s", output_bitrate);
+        info!(
+            "Pixel format: {:?} ({} bit), working format: {:?}, transfer: {:?}",
+            decoder.format(),
+            bit_depth(decoder.format()),
+            color_mapping.format,
+            color_mapping.transfer
+        );
+        if !hdr_metadata.is_empty() {
+            info!("HDR static metadata found, will be copied to output");
+        }
+    }
+    let preset = if proxy.is_some() {
+        PROXY_PRESET
+    } else {
+        arg.x264_preset.as_str()
+    };
+    let threading_config = Config {
+        kind: ffmpeg_next::threading::Type::Frame,
+        safe: true,
+        count: arg.worker_count as usize,
+    };
+    // 编码参数取自解码器; 可恢复模式下每个分段重新打开编码器, 使各分段以关键帧开始
+    let decoder_ref = unsafe { *decoder.as_ptr() };
+    let (aspect_ratio, output_format, color_space, color_range) = (
+        decoder.aspect_ratio(),
+        encoder_format(libx264, decoder.format()),
+        decoder.color_space(),
+        decoder.color_range(),
+    );
+    let input_time_base = input_video.time_base();
+    let open_video_encoder = || -> anyhow::Result<encoder::Video> {
+        // let mut context_encoder = codec::context::Context::from_parameters(output_video.parameters())?;
+        let mut context_encoder =
+            unsafe { Context::wrap(avcodec_alloc_context3(libx264.as_ptr()), None) };
+        // context_encoder.set_flags(ffmpeg_next::codec::Flags::GLOBAL_HEADER);
+        // let fps = input_video.avg_frame_rate();
+        unsafe {
+            let time_base = AVRational {
+                num: input_time_base.numerator(),
+                den: input_time_base.denominator(),
+            };
+            let time_base_inv = AVRational {
+                num: input_time_base.denominator(),
+                den: input_time_base.numerator(),
+            };
+            debug!(
+                "Initial context pointer: {:?}",
+                context_encoder.as_mut_ptr()
+            );
+            let mut context = *context_encoder.as_mut_ptr();
+            let codec = *libx264.as_ptr();
+            context.codec_id = codec.id;
+            context.codec_type = codec.type_;
+            context.width = frame_width as i32;
+            context.height = frame_height as i32;
+            context.pix_fmt = decoder_ref.pix_fmt;
+            context.bit_rate = decoder_ref.bit_rate;
+            context.framerate = time_base;
+            context.time_base = time_base_inv;
+            context.gop_size = decoder_ref.gop_size;
+            context.qmax = decoder_ref.qmax;
+            context.qmin = decoder_ref.qmin;
+            context.max_b_frames = decoder_ref.max_b_frames;
+            // context.pkt_timebase
+            debug!("Context encoder: {:#?}", context);
+        }
+        // avcodec_open2(avctx, codec, options)
+        debug!("Context medium: {:?}", context_encoder.medium());
+        let mut video_encoder = context_encoder.encoder().video()?;
+
+        video_encoder.set_height(frame_height);
+        video_encoder.set_width(frame_width);
+        video_encoder.set_aspect_ratio(aspect_ratio);
+        video_encoder.set_format(output_format);
+        video_encoder.set_frame_rate(Some(avg_fps));
+        video_encoder.set_time_base(input_time_base);
+        video_encoder.set_bit_rate(output_bitrate);
+        video_encoder.set_colorspace(color_space);
+        video_encoder.set_color_range(color_range);
+        unsafe {
+            video_encoder.set_gop(decoder_ref.gop_size as u32);
+            video_encoder.set_qmax(decoder_ref.qmax as _);
+            video_encoder.set_qmin(decoder_ref.qmin as _);
+            video_encoder.set_max_b_frames(decoder_ref.max_b_frames as _);
+            let encoder_ref = video_encoder.as_mut_ptr();
+            (*encoder_ref).color_primaries = decoder_ref.color_primaries;
+            (*encoder_ref).color_trc = decoder_ref.color_trc;
+            (*encoder_ref).chroma_sample_location = decoder_ref.chroma_sample_location;
+        }
+        if arg.resume {
+            // 分段拼接时解码时间戳须连续递增, 不使用B帧
+            video_encoder.set_max_b_frames(0);
+        }
+        unsafe {
+            let val_ref = *video_encoder.as_ptr();
+            debug!("Before open, context: {:#?}", val_ref);
+            debug!("Pointer: {:?}", video_encoder.as_ptr());
+        }
+        let mut video_encoder = video_encoder.open_as_with(libx264, {
+            let mut dict = Dictionary::new();
+            dict.set("preset", preset);
+            if arg.x264_tune != "none" {
+                dict.set("tune", &arg.x264_tune);
+            }
+            // 不指定profile, 由libx264按像素格式选择
+            if let Some(params) = hdr_metadata.x264_params() {
+                debug!("x264-params: {}", params);
+                dict.set("x264-params", &params);
+            }
+            dict
+        })?;
+        video_encoder.set_threading(threading_config.clone());
+        return Ok(video_encoder);
+    };
+    let mut video_encoder = open_video_encoder()?;
+
+    unsafe {
+        let mut stream = output_ctx.stream_mut(output_video_index).unwrap();
+        let stream_ref = *stream.as_mut_ptr();
+        let code = avcodec_parameters_from_context(stream_ref.codecpar, video_encoder.as_ptr());
+        if code != 0 {
+            return Err(anyhow!("Failed to copy parameters from context: {}", code));
+        }
+    }
+
+    output_ctx
+        .stream_mut(output_video_index)
+        .unwrap()
+        .set_parameters(&video_encoder);
+    unsafe {
+        let mut stream = output_ctx.stream_mut(output_video_index).unwrap();
+        hdr_metadata
+            .copy_to_stream(stream.as_mut_ptr())
+            .map_err(|e| anyhow!("Failed to copy HDR metadata: {}", e))?;
+    }
+
+    output_ctx.write_header_with({
+        let mut dict = Dictionary::new();
+        if arg.subtitle_tag.is_some() {
+            // mp4默认只写入标准的metadata键
+            dict.set("movflags", "use_metadata_tags");
+        }
+        dict
+    })?;
+
+    let (mut subtitles, ignored_subtitle_files) = match source {
+        Some(mut source) => load_source(source.as_mut()),
+        None => load_subtitles_counted(&PathBuf::from(&arg.subtitle_files)),
+    }
+    .map_err(|e| anyhow!("Failed to read subtitles: {}\n", e))?;
+    info!("{} subtitles loaded.", subtitles.len());
+    map_subtitles(&mut subtitles, &color_mapping)?;
+    if let Some(proxy) = &proxy {
+        scale_subtitles(&mut subtitles, proxy)?;
+    }
+
+    let mut render_data = Vec::<RenderData>::new();
+    let subtitle_conflicts = init_render_data(
+        &mut render_data,
+        &subtitles,
+        total_frames,
+        arg.top_offset,
+        arg.bottom_offset,
+    )?;
+    info!("Render data length: {}", render_data.len());
+    let mut scaler_output = ffmpeg_next::software::scaling::Context::get(
+        color_mapping.format,
+        frame_width,
+        frame_height,
+        video_encoder.format(),
+        video_encoder.width(),
+        video_encoder.height(),
+        Flags::BILINEAR,
+    )?;
+
+    ffmpeg_next::format::context::output::dump(&output_ctx, 0, Some(&arg.output));
+    // 作为库多次调用时全局线程池已存在, 沿用之前的设置
+    match ThreadPoolBuilder::new()
+        .num_threads(arg.worker_count as usize)
+        .build_global()
+    {
+        Ok(()) => info!("Rayon threadpool initialized."),
+        Err(e) => debug!("Rayon threadpool already initialized: {}", e),
+    }
+    let mut embedder = SubtitleEmbedder::new(
+        &render_data,
+        arg.chunk_size as usize,
+        proxy.map_or(arg.top_offset, |proxy| proxy.scale_offset(arg.top_offset)),
+        proxy.map_or(arg.bottom_offset, |proxy| {
+            proxy.scale_offset(arg.bottom_offset)
+        }),
+    );
+    let mut output_frame_idx: i64 = 0;
+    // 编码器输出的视频包数, 即实际写入的帧数
+    let mut encoded_frame_count: i64 = 0;
+    let mut input_frame_idx: i64 = 0;
+    // 剪辑后保留的帧数, 即输出视频的帧号
+    let mut kept_frame_idx: i64 = 0;
+    // 没有字幕, 未经合成的帧数
+    let mut bypassed_frame_count: i64 = 0;
+    let origin = input_origin(input_ctx);
+    // 拼接后的时间戳均以第一个输入文件中各流的time base为单位
+    let stream_time_bases = input_ctx
+        .streams()
+        .map(|stream| stream.time_base())
+        .collect::<Vec<Rational>>();
+    let decoder_timebase = stream_time_bases[video_stream_index];
+    let output_timebase = output_ctx.stream(output_video_index).unwrap().time_base();
+    let mut segments = if arg.resume {
+        let key = JobKey {
+            input: inputs[0].clone(),
+            subtitle_files: arg.subtitle_files.clone(),
+            width: frame_width,
+            height: frame_height,
+            chunk_size: arg.chunk_size,
+            settings: encoding_settings(arg),
+        };
+        Some(Segments::open(&arg.output, key, decoder_timebase)?)
+    } else {
+        None
+    };
+    // 已完成分段中的最后一帧, 续压时跳过此前的帧
+    let mut resume_from = segments
+        .as_ref()
+        .map_or(0, |segments| segments.frames_done());
+    encoded_frame_count += segments
+        .as_ref()
+        .map_or(0, |segments| segments.packets_done());
+    let mut start_frame: i64 = 1;
+    let mut output_packet = Packet::empty();
+
+    let mut write_output = |embedder: &mut SubtitleEmbedder,
+                            output_ctx: &mut Output,
+                            progress: &mut Progress,
+                            start_frame: i64,
+                            end_frame: i64|
+     -> anyhow::Result<()> {
+        let embed_start = std::time::Instant::now();
+        let video_sec = (end_frame - start_frame + 1) as f64
+            / (avg_fps.numerator() as f64 / avg_fps.denominator() as f64);
+        log::log!(
+            progress.chunk_log_level(),
+            "Starting embedding for flap {} to {}, video secs {}",
+            start_frame, end_frame, video_sec
+        );
+        embedder
+            .embed(start_frame, end_frame)
+            .map_err(|e| anyhow!("Failed to perform embedding: {}", e))?;
+        log::log!(
+            progress.chunk_log_level(),
+            "Embedding done for flap {} to {}",
+            start_frame,
+            end_frame
+        );
+        progress.stage(
+            Stage::Embed,
+            end_frame - start_frame + 1,
+            video_sec,
+            embed_start.elapsed().as_secs_f64(),
+        );
+        let mut output_frame = Video::empty();
+
+        log::log!(progress.chunk_log_level(), "Chunk encoding started.");
+        let encode_start = std::time::Instant::now();
+        for frame in embedder.get_buf().iter() {
+            output_frame_idx += 1;
+            let pts = frame.pts();
+            scaler_output
+                .run(&frame, &mut output_frame)
+                .map_err(|e| anyhow!("Failed to run output scaler: {}", e))?;
+
+            output_frame.set_pts(pts);
+            video_encoder
+                .send_frame(&output_frame)
+                .map_err(|e| anyhow!("Failed to send frame to video encoder: {}", e))?;
+            // 写输出
+            while video_encoder.receive_packet(&mut output_packet).is_ok() {
+                encoded_frame_count += 1;
+                match segments.as_mut() {
+                    Some(segments) => segments.write(&mut output_packet, &video_encoder)?,
+                    None => {
+                        output_packet.set_stream(output_video_index);
+                        output_packet.rescale_ts(decoder_timebase, output_timebase);
+                        output_packet
+                            .write_interleaved(output_ctx)
+                            .map_err(|e| anyhow!("Failed to write output stream: {}", e))?;
+                    }
+                }
+            }
+        }
+        if let Some(segments) = segments.as_mut() {
+            // 取出编码器中的剩余包, 关闭分段后换用新的编码器
+            video_encoder.send_eof()?;
+            while video_encoder.receive_packet(&mut output_packet).is_ok() {
+                encoded_frame_count += 1;
+                segments.write(&mut output_packet, &video_encoder)?;
+            }
+            segments.close(start_frame, end_frame)?;
+            video_encoder = open_video_encoder()?;
+        }
+        progress.stage(
+            Stage::Encode,
+            end_frame - start_frame + 1,
+            video_sec,
+            encode_start.elapsed().as_secs_f64(),
+        );
+        embedder.finish();
+        log::log!(
+            progress.chunk_log_level(),
+            "Frame done: {} to {}",
+            start_frame,
+            end_frame
+        );
+
+        return Ok(());
+    };
+    let mut progress = Progress::new(
+        arg.progress.resolve(),
+        arg.progress_fd,
+        total_frames,
+        f64::from(avg_fps),
+        hooks.on_progress.take(),
+    )?;
+    let mut last_decode_start = std::time::Instant::now();
+    let mut curr_decoding = false;
+    let (width, height, pixel_format) = (decoder.width(), decoder.height(), decoder.format());
+    let mut first_decoder = Some(decoder);
+    // 当前输入文件在拼接后时间轴上的起始时间(秒)
+    let mut part_start = 0.0;
+    // 请求取消后停止读取, 写完已解码的帧后正常收尾
+    let mut cancelled = false;
+    'parts: for (part_idx, part_ctx) in input_ctxs.iter_mut().enumerate() {
+        let part_offset = PartOffset {
+            seconds: part_start + origin - input_origin(part_ctx),
+        };
+        let part_video_timebase = part_ctx.stream(video_stream_index).unwrap().time_base();
+        let mut decoder = match first_decoder.take() {
+            Some(decoder) => decoder,
+            None => open_video_decoder(&part_ctx.stream(video_stream_index).unwrap())?,
+        };
+        decoder.set_threading(threading_config.clone());
+        if decoder.width() != width
+            || decoder.height() != height
+            || decoder.format() != pixel_format
+        {
+            warn!(
+                "{}: video is {}x{} {:?}, scaling to {}x{} {:?}",
+                inputs[part_idx],
+                decoder.width(),
+                decoder.height(),
+                decoder.format(),
+                width,
+                height,
+                pixel_format
+            );
+        }
+        let mut scaler_input = ffmpeg_next::software::scaling::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            color_mapping.format,
+            frame_width,
+            frame_height,
+            Flags::BILINEAR,
+        )?;
+        if inputs.len() > 1 {
+            info!(
+                "Processing input {} ({}/{}), timeline offset: {:.3} secs",
+                inputs[part_idx],
+                part_idx + 1,
+                inputs.len(),
+                part_start
+            );
+        }
+        let to_timeline = |ts: i64| {
+            part_offset.shift(
+                ts.rescale(part_video_timebase, decoder_timebase),
+                decoder_timebase,
+            )
+        };
+        let part_first_frame = input_frame_idx;
+        if resume_from > 0 {
+            info!("Decoding {} frames already encoded", resume_from);
+        }
+        // 输入结束后向解码器发送EOF, 取出其中缓存的剩余帧
+        for item in part_ctx.packets().map(Some).chain(std::iter::once(None)) {
+            if hooks.cancel.is_cancelled() {
+                cancelled = true;
+                break 'parts;
+            }
+            match item {
+                Some((input_stream, mut input_packet))
+                    if input_stream.index() != video_stream_index =>
+                {
+                    // 可恢复模式下其他流在拼接分段时写入
+                    if arg.resume {
+                        continue;
+                    }
+                    if let Some(output_idx) = stream_mapping[input_stream.index()] {
+                        let time_base = stream_time_bases[input_stream.index()];
+                        input_packet.rescale_ts(input_stream.time_base(), time_base);
+                        input_packet.set_pts(input_packet.pts().map(|ts| part_offset.shift(ts, time_base)));
+                        input_packet.set_dts(input_packet.dts().map(|ts| part_offset.shift(ts, time_base)));
+                        if let Some(trim) = &trim {
+                            if !trim.shift_packet(&mut input_packet, time_base) {
+                                continue;
+                            }
+                        }
+                        if let Some(transcoder) = audio_transcoders.get_mut(&input_stream.index()) {
+                            transcoder.send_packet(&input_packet, &mut output_ctx)?;
+                            continue;
+                        }
+                        let output_stream = output_ctx.stream(output_idx).unwrap();
+                        input_packet.rescale_ts(time_base, output_stream.time_base());
+                        input_packet.set_stream(output_stream.index());
+                        input_packet.set_position(-1);
+                        input_packet
+                            .write_interleaved(&mut output_ctx)
+                            .map_err(|e| anyhow!("Failed to write packet: {}", e))?;
+                    }
+                    continue;
+                }
+                Some((_, input_packet)) => decoder
+                    .send_packet(&input_packet)
+                    .map_err(|e| anyhow!("Failed to send packet to decoder: {}", e))?,
+                None => decoder
+                    .send_eof()
+                    .map_err(|e| anyhow!("Failed to send EOF to decoder: {}", e))?,
+            }
+            let mut decoded = Video::empty();
+            if !curr_decoding {
+                log::log!(progress.chunk_log_level(), "");
+                log::log!(progress.chunk_log_level(), "Chunk decoding started.");
+                curr_decoding = true;
+            }
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                // debug!("Input frame {}, pts: {:?}", input_frame_idx, decoded.pts());
+                if resume_from > 0 {
+                    // 与完整压制相同按解码顺序计帧号, 可变帧率的输入中字幕也落在相同的帧上;
+                    // 已完成分段中的帧只解码不处理
+                    input_frame_idx += 1;
+                    if input_frame_idx == resume_from {
+                        kept_frame_idx = resume_from;
+                        start_frame = resume_from + 1;
+                        resume_from = 0;
+                    }
+                    continue;
+                }
+                input_frame_idx += 1;
+                progress.tick(input_frame_idx);
+                let pts = match &trim {
+                    Some(trim) => match decoded
+                        .timestamp()
+                        .map(to_timeline)
+                        .and_then(|ts| trim.shift_ts(ts, decoder_timebase))
+                    {
+                        Some(pts) => Some(pts),
+                        None => continue,
+                    },
+                    None => decoded.pts().map(to_timeline),
+                };
+                let mut rgb_frame = Video::empty();
+                unsafe {
+                    rgb_frame.set_format(color_mapping.format);
+                    rgb_frame.set_width(frame_width);
+                    rgb_frame.set_height(frame_height);
+                    let err = av_frame_get_buffer(rgb_frame.as_mut_ptr(), 32);
+                    if err != 0 {
+                        let e = Error::from(err);
+                        return Err(anyhow!(
+                            "Failed to get buffer for rgb_frame: {}, {}",
+                            err,
+                            e
+                        ));
+                    }
+                }
+                scaler_input
+                    .run(&decoded, &mut rgb_frame)
+                    .map_err(|e| anyhow!("Failed to run input scaler: {}. This should not happen, consider your memory usage.", e))?;
+                rgb_frame.set_pts(pts);
+                kept_frame_idx += 1;
+                if render_data
+                    .get(input_frame_idx as usize - 1)
+                    .map_or(true, |data| data.major.is_none() && data.minor.is_none())
+                {
+                    bypassed_frame_count += 1;
+                }
+                if embedder
+                    .send_frame(rgb_frame, input_frame_idx as usize)
+                    .map_err(|e| anyhow!("Failed to send frame to embedder: {}", e))?
+                {
+                    let video_sec = (kept_frame_idx - start_frame + 1) as f64
+                        / (avg_fps.numerator() as f64 / avg_fps.denominator() as f64);
+                    log::log!(progress.chunk_log_level(), "Chunk decoding done.");
+                    progress.stage(
+                        Stage::Decode,
+                        kept_frame_idx - start_frame + 1,
+                        video_sec,
+                        last_decode_start.elapsed().as_secs_f64(),
+                    );
+                    write_output(
+                        &mut embedder,
+                        &mut output_ctx,
+                        &mut progress,
+                        start_frame,
+                        kept_frame_idx,
+                    )?;
+                    start_frame = kept_frame_idx + 1;
+                    last_decode_start = std::time::Instant::now();
+                    curr_decoding = false;
+                }
+            }
+        }
+        part_start += input_duration(part_ctx).unwrap_or(
+            (input_frame_idx - part_first_frame) as f64
+                / (avg_fps.numerator() as f64 / avg_fps.denominator() as f64),
+        );
+    }
+    progress.set_frames_done(input_frame_idx);
+    if !embedder.get_buf().is_empty() {
+        write_output(
+            &mut embedder,
+            &mut output_ctx,
+            &mut progress,
+            start_frame,
+            kept_frame_idx,
+        )?;
+    }
+    if cancelled {
+        warn!(
+            "Cancelled, output stops at frame {} ({:.3} secs)",
+            kept_frame_idx,
+            kept_frame_idx as f64 / f64::from(avg_fps)
+        );
+    }
+
+    // 取出编码器中因lookahead和B帧缓存的剩余包
+    video_encoder.send_eof()?;
+    while video_encoder.receive_packet(&mut output_packet).is_ok() {
+        encoded_frame_count += 1;
+        output_packet.set_stream(output_video_index);
+        output_packet.rescale_ts(decoder_timebase, output_timebase);
+        output_packet
+            .write_interleaved(&mut output_ctx)
+            .map_err(|e| anyhow!("Failed to write output stream: {}", e))?;
+    }
+    if let Some(segments) = &segments {
+        // 按时间顺序合并各分段中的视频与输入文件中的其他流
+        info!("Stitching segments into {}", arg.output);
+        let mut stitch_input = input(&inputs[0])
+            .map_err(|e| anyhow!("Failed to open video file {}: {}", inputs[0], e))?;
+        let mut video_packets = segments.reader();
+        let mut next_video = video_packets.next_packet(output_timebase)?;
+        for (input_stream, mut input_packet) in stitch_input.packets() {
+            let output_idx = match stream_mapping[input_stream.index()] {
+                Some(output_idx) if input_stream.index() != video_stream_index => output_idx,
+                _ => continue,
+            };
+            let time_base = input_stream.time_base();
+            while let Some(video_packet) = next_video.as_mut() {
+                if let (Some(video_dts), Some(dts)) = (video_packet.dts(), input_packet.dts()) {
+                    if video_dts.rescale(output_timebase, time_base) > dts {
+                        break;
+                    }
+                }
+                video_packet.set_stream(output_video_index);
+                video_packet
+                    .write_interleaved(&mut output_ctx)
+                    .map_err(|e| anyhow!("Failed to write output stream: {}", e))?;
+                next_video = video_packets.next_packet(output_timebase)?;
+            }
+            // 取消时其他流只写到视频结束处
+            if cancelled && next_video.is_none() {
+                break;
+            }
+            if let Some(transcoder) = audio_transcoders.get_mut(&input_stream.index()) {
+                transcoder.send_packet(&input_packet, &mut output_ctx)?;
+                continue;
+            }
+            let output_stream = output_ctx.stream(output_idx).unwrap();
+            input_packet.rescale_ts(time_base, output_stream.time_base());
+            input_packet.set_stream(output_stream.index());
+            input_packet.set_position(-1);
+            input_packet
+                .write_interleaved(&mut output_ctx)
+                .map_err(|e| anyhow!("Failed to write packet: {}", e))?;
+        }
+        while let Some(mut video_packet) = next_video {
+            video_packet.set_stream(output_video_index);
+            video_packet
+                .write_interleaved(&mut output_ctx)
+                .map_err(|e| anyhow!("Failed to write output stream: {}", e))?;
+            next_video = video_packets.next_packet(output_timebase)?;
+        }
+    }
+    for transcoder in audio_transcoders.values_mut() {
+        transcoder.finish(&mut output_ctx)?;
+    }
+    output_ctx.write_trailer()?;
+    if let Some(segments) = segments {
+        // 取消时保留分段, 以便续压
+        if !cancelled {
+            segments.remove();
+        }
+    }
+    unsafe {
+        let final_stream = *output_ctx.stream(output_video_index).unwrap().as_ptr();
+        debug!("Final stream: {:#?}", final_stream.nb_frames);
+    }
+    info!(
+        "Frames decoded: {}, kept: {}, sent to encoder: {}, encoded: {}",
+        input_frame_idx, kept_frame_idx, output_frame_idx, encoded_frame_count
+    );
+    if encoded_frame_count != kept_frame_idx {
+        warn!(
+            "Encoded frame count {} does not match kept frame count {}",
+            encoded_frame_count, kept_frame_idx
+        );
+    }
+    progress.finish();
+    if let Some(path) = &arg.summary {
+        let output_size = std::fs::metadata(&arg.output).map(|m| m.len()).ok();
+        let output_duration = kept_frame_idx as f64 / f64::from(avg_fps);
+        let summary = Summary {
+            version: env!("CARGO_PKG_VERSION"),
+            inputs: input_summaries,
+            source: VideoSummary {
+                width,
+                height,
+                pixel_format: format!("{:?}", pixel_format),
+                fps: f64::from(avg_fps),
+            },
+            output: OutputSummary {
+                file: arg.output.clone(),
+                video: VideoSummary {
+                    width: frame_width,
+                    height: frame_height,
+                    pixel_format: format!("{:?}", video_encoder.format()),
+                    fps: f64::from(avg_fps),
+                },
+                size: output_size,
+                duration: output_duration,
+                bitrate: output_size
+                    .filter(|_| output_duration > 0.0)
+                    .map(|size| size as f64 * 8.0 / output_duration),
+            },
+            encoder: EncoderSummary {
+                codec: libx264.name().to_string(),
+                preset: preset.to_string(),
+                pixel_format: format!("{:?}", video_encoder.format()),
+                bitrate: output_bitrate,
+                threads: arg.worker_count,
+                proxy_height: proxy.map(|proxy| proxy.height),
+                audio_transcoded_streams: audio_transcoders.len(),
+            },
+            subtitles: SubtitleSummary {
+                loaded: subtitles.len(),
+                ignored: ignored_subtitle_files,
+                conflicting_frames: subtitle_conflicts,
+            },
+            frames: FrameSummary {
+                decoded: input_frame_idx,
+                kept: kept_frame_idx,
+                trimmed: input_frame_idx - kept_frame_idx,
+                bypassed: bypassed_frame_count,
+                encoded: encoded_frame_count,
+            },
+            stages: StageSummary::from_progress(&progress),
+            elapsed: progress.elapsed(),
+        };
+        summary.write(Path::new(path))?;
+        info!("Summary written to {}", path);
+    }
+    if cancelled {
+        return Err(anyhow!("Cancelled after frame {}", kept_frame_idx));
+    }
+    info!("Done!");
+    return Ok(());
+}
+
+/// 编码器所用的像素格式: 编码器支持源格式时直接使用, 否则改用色度采样相同的8位格式
+///
+/// 常见的libx264构建只支持8位, 高位深的输入此时降为8位编码
+fn encoder_format(codec: Codec, format: Pixel) -> Pixel {
+    let supported = codec
+        .video()
+        .ok()
+        .and_then(|video| video.formats())
+        .map(|formats| formats.collect::<Vec<Pixel>>());
+    match supported {
+        Some(formats) if !formats.contains(&format) => {
+            let fallback = match format.descriptor() {
+                Some(desc) if desc.log2_chroma_w() == 0 && desc.log2_chroma_h() == 0 => {
+                    Pixel::YUV444P
+                }
+                Some(desc) if desc.log2_chroma_h() == 0 => Pixel::YUV422P,
+                _ => Pixel::YUV420P,
+            };
+            warn!(
+                "{} does not support {:?} ({} bit), encoding as {:?}",
+                codec.name(),
+                format,
+                bit_depth(format),
+                fallback
+            );
+            fallback
+        }
+        _ => format,
+    }
+}
+
+// #[inline]
+// fn timestamp() -> f64 {
+//     let value = std::time::SystemTime::now()
+//         .duration_since(std::time::UNIX_EPOCH)
+//         .unwrap()
+//         .as_secs_f64();
+//     return value;
+// }
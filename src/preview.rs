@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use ffmpeg_next::{format::Pixel, frame::Video};
+use log::info;
+
+use crate::{
+    cmdline::{InputArg, PreviewArg},
+    embedder::SubtitleEmbedder,
+    image::save_png,
+    reader::FrameReader,
+    render::{init_render_data, subtitle_frames, RenderData},
+    subtitle::load_subtitles,
+};
+
+/// 用与压制相同的合成流程, 将字幕叠加到单帧上
+pub fn compose(
+    render_data: &Vec<RenderData>,
+    frame: Video,
+    frame_number: i64,
+    arg: &InputArg,
+) -> anyhow::Result<Video> {
+    let mut embedder = SubtitleEmbedder::new(render_data, 1, arg.top_offset, arg.bottom_offset);
+    embedder.send_frame(frame, frame_number as usize)?;
+    embedder.embed(1, 1)?;
+    return Ok(embedder.get_buf().pop().unwrap());
+}
+
+/// 跳转到指定帧, 合成字幕后保存为PNG
+pub fn run(arg: &InputArg, preview_arg: &PreviewArg) -> anyhow::Result<()> {
+    let input_name = arg
+        .input
+        .first()
+        .ok_or(anyhow!("No input file specified"))?;
+    let mut reader = FrameReader::open(input_name, arg.video_stream, Pixel::RGB24, None)?;
+    let fps = f64::from(reader.frame_rate());
+    let frame_number = match (preview_arg.frame, preview_arg.time) {
+        (Some(frame), _) => frame,
+        (None, Some(time)) => (time * fps).floor() as i64 + 1,
+        (None, None) => return Err(anyhow!("Either --frame or --time is required")),
+    };
+    if frame_number < 1 {
+        return Err(anyhow!("Frame number starts from 1"));
+    }
+    let subtitles = load_subtitles(&PathBuf::from(&arg.subtitle_files))
+        .map_err(|e| anyhow!("Failed to read subtitles: {}\n", e))?;
+    let mut render_data = Vec::<RenderData>::new();
+    init_render_data(
+        &mut render_data,
+        &subtitles,
+        subtitle_frames(&subtitles).max(frame_number),
+        arg.top_offset,
+        arg.bottom_offset,
+    )?;
+    let active = &render_data[frame_number as usize - 1];
+    info!(
+        "Frame {} ({:.3} secs): major subtitle {:?}, minor subtitle {:?}",
+        frame_number,
+        (frame_number - 1) as f64 / fps,
+        active.major.as_ref().map(|s| s.id),
+        active.minor.as_ref().map(|s| s.id)
+    );
+
+    let frame = reader.read_frame(frame_number)?;
+    let image = compose(&render_data, frame, frame_number, arg)?;
+    let path = preview_arg
+        .image
+        .clone()
+        .unwrap_or(format!("preview-{}.png", frame_number));
+    save_png(&image, Path::new(&path))?;
+    info!("Preview saved to {}", path);
+    return Ok(());
+}
@@ -0,0 +1,347 @@
+use std::{
+    io::Write,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use log::{info, warn};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    // 交互终端中显示进度条, 否则打印日志
+    Auto,
+    Log,
+    Json,
+    Bar,
+}
+
+impl FromStr for ProgressMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "log" => Ok(Self::Log),
+            "json" => Ok(Self::Json),
+            "bar" => Ok(Self::Bar),
+            _ => Err(format!("Unsupported progress mode: {}", s)),
+        }
+    }
+}
+
+impl ProgressMode {
+    pub fn resolve(self) -> Self {
+        match self {
+            // 标准输出被重定向时 (如 > run.log) 仍输出日志
+            Self::Auto if atty::is(atty::Stream::Stdout) && atty::is(atty::Stream::Stderr) => {
+                Self::Bar
+            }
+            Self::Auto => Self::Log,
+            mode => mode,
+        }
+    }
+}
+
+// 进度条的宽度与最短刷新间隔
+const BAR_WIDTH: usize = 30;
+const BAR_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Decode,
+    Embed,
+    Encode,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 3] = [Stage::Decode, Stage::Embed, Stage::Encode];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Decode => "decode",
+            Self::Embed => "embed",
+            Self::Encode => "encode",
+        }
+    }
+}
+
+/// 传给进度回调的进度信息
+#[derive(Debug, Clone)]
+pub struct ProgressUpdate {
+    pub frames_done: i64,
+    pub total_frames: i64,
+    // 整体的处理帧率
+    pub fps: f64,
+    pub elapsed: f64,
+    pub eta: Option<f64>,
+    pub finished: bool,
+}
+
+/// 作为库调用时的进度回调, 调用间隔与进度条的刷新间隔相同
+pub type ProgressCallback = Box<dyn FnMut(&ProgressUpdate) + Send>;
+
+/// 每行一个的JSON进度事件
+#[derive(Serialize)]
+struct Event<'a> {
+    event: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stage: Option<&'a str>,
+    // 已解码的输入帧数
+    frames_done: i64,
+    total_frames: i64,
+    // 本阶段处理的帧数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk_frames: Option<i64>,
+    // 本阶段的处理速度 (视频时长 / 耗时)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed: Option<f64>,
+    // 整体的处理帧率
+    fps: f64,
+    elapsed: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eta: Option<f64>,
+}
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// 压制进度的输出, 日志模式下打印各阶段速度, json模式下输出结构化事件, 进度条模式下刷新终端中的一行
+pub struct Progress {
+    mode: ProgressMode,
+    sink: Box<dyn Write>,
+    total_frames: i64,
+    frames_done: i64,
+    // 视频帧率, 用于计算相对实时的速度
+    video_fps: f64,
+    start: Instant,
+    last_draw: Option<Instant>,
+    // 各阶段累计的 (耗时, 视频时长), 按Stage::ALL的顺序
+    stage_totals: [(f64, f64); 3],
+    callback: Option<ProgressCallback>,
+    last_callback: Option<Instant>,
+}
+
+impl Progress {
+    /// mode须已resolve; fd只在json模式下使用, 为None时JSON事件写入标准输出
+    pub fn new(
+        mode: ProgressMode,
+        fd: Option<i32>,
+        total_frames: i64,
+        video_fps: f64,
+        callback: Option<ProgressCallback>,
+    ) -> anyhow::Result<Self> {
+        let sink: Box<dyn Write> = match (mode, fd) {
+            (ProgressMode::Json, Some(fd)) => open_fd(fd)?,
+            (_, Some(fd)) => {
+                warn!("--progress-fd {} is ignored without --progress=json", fd);
+                Box::new(std::io::stdout())
+            }
+            (_, None) => Box::new(std::io::stdout()),
+        };
+        let mut progress = Self {
+            mode,
+            sink,
+            total_frames,
+            frames_done: 0,
+            video_fps,
+            start: Instant::now(),
+            last_draw: None,
+            stage_totals: [(0.0, 0.0); 3],
+            callback,
+            last_callback: None,
+        };
+        progress.emit("start", None, None, None);
+        return Ok(progress);
+    }
+    /// 每块的过程日志级别, 显示进度条时降为debug
+    pub fn chunk_log_level(&self) -> log::Level {
+        if self.mode == ProgressMode::Bar {
+            log::Level::Debug
+        } else {
+            log::Level::Info
+        }
+    }
+    pub fn set_frames_done(&mut self, frames_done: i64) {
+        self.frames_done = frames_done;
+    }
+    /// 每解码一帧调用, 进度条模式下按间隔刷新
+    pub fn tick(&mut self, frames_done: i64) {
+        self.frames_done = frames_done;
+        if self.mode == ProgressMode::Bar
+            && self
+                .last_draw
+                .map_or(true, |last| last.elapsed() >= BAR_INTERVAL)
+        {
+            self.draw();
+        }
+        if self
+            .last_callback
+            .map_or(true, |last| last.elapsed() >= BAR_INTERVAL)
+        {
+            self.notify(false);
+        }
+    }
+    fn notify(&mut self, finished: bool) {
+        if self.callback.is_none() {
+            return;
+        }
+        let (elapsed, fps, eta) = self.rates();
+        let update = ProgressUpdate {
+            frames_done: self.frames_done,
+            total_frames: self.total_frames,
+            fps,
+            elapsed,
+            eta,
+            finished,
+        };
+        if let Some(callback) = self.callback.as_mut() {
+            callback(&update);
+            self.last_callback = Some(Instant::now());
+        }
+    }
+    /// 整体的 (耗时, 处理帧率, 预计剩余时间)
+    fn rates(&self) -> (f64, f64, Option<f64>) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let fps = if elapsed > 0.0 {
+            self.frames_done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta = if fps > 0.0 && self.total_frames > 0 {
+            Some((self.total_frames - self.frames_done).max(0) as f64 / fps)
+        } else {
+            None
+        };
+        return (elapsed, fps, eta);
+    }
+    fn draw(&mut self) {
+        let (elapsed, fps, eta) = self.rates();
+        let ratio = if self.total_frames > 0 {
+            (self.frames_done as f64 / self.total_frames as f64).min(1.0)
+        } else {
+            0.0
+        };
+        let filled = (ratio * BAR_WIDTH as f64) as usize;
+        let speed = if self.video_fps > 0.0 {
+            fps / self.video_fps
+        } else {
+            0.0
+        };
+        // 进度条画在标准错误上, 与标准输出上的日志分开
+        let mut stderr = std::io::stderr();
+        let _ = write!(
+            stderr,
+            "\r[{}{}] {:5.1}% {}/{} frames | {:.2}x | elapsed {} | ETA {}\x1b[K",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            ratio * 100.0,
+            self.frames_done,
+            self.total_frames,
+            speed,
+            format_duration(elapsed),
+            eta.map_or("--:--:--".to_string(), format_duration)
+        );
+        let _ = stderr.flush();
+        self.last_draw = Some(Instant::now());
+    }
+    /// 某一阶段处理完一块, video_secs为该块的视频时长, secs为耗时
+    pub fn stage(&mut self, stage: Stage, chunk_frames: i64, video_secs: f64, secs: f64) {
+        let speed = video_secs / secs;
+        let total = &mut self.stage_totals[stage as usize];
+        total.0 += secs;
+        total.1 += video_secs;
+        match self.mode {
+            ProgressMode::Log => {
+                let name = match stage {
+                    Stage::Decode => "Decoding",
+                    Stage::Embed => "Embedding",
+                    Stage::Encode => "Encoding",
+                };
+                info!(
+                    "{} speed: {:.3}x, time usage: {:.4} secs",
+                    name, speed, secs
+                );
+            }
+            ProgressMode::Json => {
+                self.emit("stage", Some(stage), Some(chunk_frames), Some(speed));
+            }
+            ProgressMode::Bar => self.draw(),
+            ProgressMode::Auto => {}
+        }
+    }
+    /// 某阶段累计的 (耗时, 视频时长)
+    pub fn stage_total(&self, stage: Stage) -> (f64, f64) {
+        self.stage_totals[stage as usize]
+    }
+    pub fn elapsed(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+    pub fn finish(&mut self) {
+        if self.mode == ProgressMode::Bar {
+            self.draw();
+            eprintln!();
+        }
+        self.emit("done", None, None, None);
+        self.notify(true);
+    }
+    fn emit(
+        &mut self,
+        event: &str,
+        stage: Option<Stage>,
+        chunk_frames: Option<i64>,
+        speed: Option<f64>,
+    ) {
+        if self.mode != ProgressMode::Json {
+            return;
+        }
+        let (elapsed, fps, eta) = self.rates();
+        let event = Event {
+            event,
+            stage: stage.map(|stage| stage.name()),
+            frames_done: self.frames_done,
+            total_frames: self.total_frames,
+            chunk_frames,
+            speed,
+            fps,
+            elapsed,
+            eta,
+        };
+        let result = serde_json::to_writer(&mut self.sink, &event)
+            .map_err(std::io::Error::from)
+            .and_then(|_| self.sink.write_all(b"\n"))
+            .and_then(|_| self.sink.flush());
+        if let Err(e) = result {
+            warn!("Failed to write progress event: {}", e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> anyhow::Result<Box<dyn Write>> {
+    use std::os::unix::io::FromRawFd;
+    if fd < 0 {
+        return Err(anyhow!("Invalid file descriptor: {}", fd));
+    }
+    // 复制一份再接管, 关闭时不影响调用方的fd (如传入1或2时的标准输出与标准错误)
+    let dup = unsafe { libc::dup(fd) };
+    if dup < 0 {
+        return Err(anyhow!(
+            "Invalid file descriptor {}: {}",
+            fd,
+            std::io::Error::last_os_error()
+        ));
+    }
+    return Ok(Box::new(unsafe { std::fs::File::from_raw_fd(dup) }));
+}
+
+#[cfg(not(unix))]
+fn open_fd(fd: i32) -> anyhow::Result<Box<dyn Write>> {
+    return Err(anyhow!(
+        "Writing progress to file descriptor {} is not supported on this platform",
+        fd
+    ));
+}
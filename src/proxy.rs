@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use ffmpeg_next::{
+    frame::Video,
+    software::scaling::{self, Flags},
+};
+use log::info;
+
+use crate::subtitle::Subtitle;
+
+// 低分辨率预览版所用的x264预设
+pub const PROXY_PRESET: &str = "ultrafast";
+
+/// 低分辨率预览版的尺寸与相对原视频的缩放比例
+#[derive(Debug, Clone, Copy)]
+pub struct Proxy {
+    pub width: u32,
+    pub height: u32,
+    pub scale: f64,
+}
+
+impl Proxy {
+    /// 按高度等比缩小, 不放大; 宽高取偶数以满足yuv420
+    pub fn new(source_width: u32, source_height: u32, height: u32) -> Self {
+        let height = height.min(source_height).max(2) & !1;
+        let scale = height as f64 / source_height as f64;
+        let width = ((source_width as f64 * scale).round() as u32).max(2) & !1;
+        info!(
+            "Proxy mode: {}x{} -> {}x{}",
+            source_width, source_height, width, height
+        );
+        return Self {
+            width,
+            height,
+            scale,
+        };
+    }
+    pub fn scale_offset(&self, offset: u32) -> u32 {
+        (offset as f64 * self.scale).round() as u32
+    }
+    /// 按面积缩小码率
+    pub fn scale_bitrate(&self, bitrate: usize) -> usize {
+        (bitrate as f64 * self.scale * self.scale) as usize
+    }
+}
+
+/// 将字幕图片缩放到预览版的分辨率
+pub fn scale_subtitles(subtitles: &mut Vec<Subtitle>, proxy: &Proxy) -> anyhow::Result<()> {
+    for subtitle in subtitles.iter_mut() {
+        let image = &subtitle.data;
+        let width = ((image.width() as f64 * proxy.scale).round() as u32).max(1);
+        let height = ((image.height() as f64 * proxy.scale).round() as u32).max(1);
+        let mut scaler = scaling::Context::get(
+            image.format(),
+            image.width(),
+            image.height(),
+            image.format(),
+            width,
+            height,
+            // 纯黑像素表示半透明, 插值会把它与相邻像素混合, 只能取最近点
+            Flags::POINT,
+        )?;
+        let mut scaled = Video::empty();
+        scaler
+            .run(image, &mut scaled)
+            .map_err(|e| anyhow!("Failed to scale subtitle {}: {}", subtitle.id, e))?;
+        subtitle.data = Arc::new(scaled);
+    }
+    return Ok(());
+}
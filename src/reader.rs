@@ -0,0 +1,195 @@
+use anyhow::anyhow;
+use ffmpeg_next::{
+    decoder,
+    format::{context::Input, input, Pixel},
+    frame::Video,
+    rescale,
+    software::scaling::{self, Flags},
+    Rational, Rescale,
+};
+
+use crate::concat::open_video_decoder;
+
+// 目标帧在当前位置之后超过该秒数时跳转, 否则继续顺序解码
+const SEEK_THRESHOLD: f64 = 10.0;
+
+/// 逐帧解码视频并转换为指定的像素格式与尺寸
+///
+/// 帧号与压制时相同, 按解码顺序从1开始计数, 不由时间戳与帧率推算, 可变帧率的视频也与压制结果一致
+pub struct FrameReader {
+    ctx: Input,
+    decoder: decoder::Video,
+    scaler: scaling::Context,
+    video_index: usize,
+    // 按显示顺序排列的各帧时间戳, 第n帧为index[n - 1]; 首次按帧号读取时扫描数据包建立
+    index: Option<Vec<i64>>,
+    // 上一次解码出的帧号, 跳转后未知
+    position: Option<i64>,
+    eof: bool,
+}
+
+impl FrameReader {
+    /// size为None时保持原视频尺寸
+    pub fn open(
+        path: &str,
+        video_stream: Option<usize>,
+        format: Pixel,
+        size: Option<(u32, u32)>,
+    ) -> anyhow::Result<Self> {
+        let ctx = input(&path).map_err(|e| anyhow!("Failed to open video file {}: {}", path, e))?;
+        let video_index = match video_stream {
+            Some(idx) => ctx
+                .stream(idx)
+                .filter(|s| s.codec().medium() == ffmpeg_next::media::Type::Video)
+                .ok_or(anyhow!("{}: stream {} is not a video stream", path, idx))?
+                .index(),
+            None => ctx
+                .streams()
+                .best(ffmpeg_next::media::Type::Video)
+                .ok_or(anyhow!("{}: failed to find video stream", path))?
+                .index(),
+        };
+        let decoder = open_video_decoder(&ctx.stream(video_index).unwrap())?;
+        let (width, height) = size.unwrap_or((decoder.width(), decoder.height()));
+        let scaler = scaling::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            format,
+            width,
+            height,
+            Flags::BILINEAR,
+        )?;
+        return Ok(Self {
+            ctx,
+            decoder,
+            scaler,
+            video_index,
+            index: None,
+            position: None,
+            eof: false,
+        });
+    }
+    pub fn width(&self) -> u32 {
+        self.decoder.width()
+    }
+    pub fn height(&self) -> u32 {
+        self.decoder.height()
+    }
+    pub fn time_base(&self) -> Rational {
+        self.ctx.stream(self.video_index).unwrap().time_base()
+    }
+    pub fn frame_rate(&self) -> Rational {
+        self.ctx.stream(self.video_index).unwrap().avg_frame_rate()
+    }
+    /// 容器中记录的帧数, 可能为0
+    pub fn frames(&self) -> i64 {
+        self.ctx.stream(self.video_index).unwrap().frames()
+    }
+    /// 扫描视频流的全部数据包, 记录各帧的时间戳
+    fn build_index(&mut self) {
+        let mut index = vec![];
+        for (stream, packet) in self.ctx.packets() {
+            if stream.index() != self.video_index {
+                continue;
+            }
+            if let Some(ts) = packet.pts().or(packet.dts()) {
+                index.push(ts);
+            }
+        }
+        // 解码输出按显示顺序, 即时间戳顺序
+        index.sort_unstable();
+        self.index = Some(index);
+        self.position = None;
+    }
+    /// 跳转到时间戳ts (视频流的时间基) 之前最近的关键帧
+    fn seek(&mut self, ts: i64) -> anyhow::Result<()> {
+        let ts = ts.rescale(self.time_base(), rescale::TIME_BASE);
+        self.ctx
+            .seek(ts, ..ts)
+            .map_err(|e| anyhow!("Failed to seek to {}: {}", ts, e))?;
+        self.decoder.flush();
+        self.position = None;
+        self.eof = false;
+        return Ok(());
+    }
+    /// 读取第frame_number帧, 目标在当前位置之前或相距较远时先跳转
+    pub fn read_frame(&mut self, frame_number: i64) -> anyhow::Result<Video> {
+        if self.index.is_none() {
+            self.build_index();
+        }
+        let index = self.index.as_ref().unwrap();
+        let target = match frame_number {
+            n if n >= 1 && n as usize <= index.len() => index[n as usize - 1],
+            _ => {
+                return Err(anyhow!(
+                    "Frame {} is beyond the end of video ({} frames)",
+                    frame_number,
+                    index.len()
+                ))
+            }
+        };
+        let need_seek = match self.position {
+            Some(position) => {
+                frame_number <= position
+                    || (target - index[position as usize - 1]) as f64 * f64::from(self.time_base())
+                        > SEEK_THRESHOLD
+            }
+            None => true,
+        };
+        if need_seek {
+            self.seek(target)?;
+        }
+        while let Some(frame) = self.next_frame()? {
+            let pts = frame.pts().unwrap_or(i64::MIN);
+            if pts == target {
+                return Ok(frame);
+            }
+            if pts > target {
+                return Err(anyhow!(
+                    "Frame {} not found, reached timestamp {} after {}",
+                    frame_number,
+                    pts,
+                    target
+                ));
+            }
+        }
+        return Err(anyhow!("Frame {} is beyond the end of video", frame_number));
+    }
+    /// 下一帧, pts为解码器给出的时间戳; 视频结束时返回None
+    pub fn next_frame(&mut self) -> anyhow::Result<Option<Video>> {
+        loop {
+            let mut decoded = Video::empty();
+            if self.decoder.receive_frame(&mut decoded).is_ok() {
+                let mut converted = Video::empty();
+                self.scaler
+                    .run(&decoded, &mut converted)
+                    .map_err(|e| anyhow!("Failed to run scaler: {}", e))?;
+                converted.set_pts(decoded.timestamp());
+                self.position = match (&self.index, decoded.timestamp()) {
+                    (Some(index), Some(ts)) => {
+                        index.binary_search(&ts).ok().map(|idx| idx as i64 + 1)
+                    }
+                    _ => None,
+                };
+                return Ok(Some(converted));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            match self.ctx.packets().next() {
+                Some((stream, packet)) => {
+                    if stream.index() == self.video_index {
+                        self.decoder
+                            .send_packet(&packet)
+                            .map_err(|e| anyhow!("Failed to send packet to decoder: {}", e))?;
+                    }
+                }
+                None => {
+                    self.decoder.send_eof()?;
+                    self.eof = true;
+                }
+            }
+        }
+    }
+}
@@ -1,9 +1,9 @@
 use std::sync::Arc;
 
-use ffmpeg_next::{frame::Video, Stream};
+use ffmpeg_next::frame::Video;
 use log::warn;
 
-use crate::{subtitle::{Subtitle, self}, cmdline::InputArg};
+use crate::subtitle::{check_subtitles, Subtitle, self};
 
 
 pub struct SubtitleWrapper {
@@ -18,21 +18,37 @@ pub struct RenderData {
     pub bottom_offset: usize,
     pub top_offset: usize,
 }
+/// 容纳全部字幕所需的最少帧数
+pub fn subtitle_frames(subtitles: &[Subtitle]) -> i64 {
+    subtitles
+        .iter()
+        .map(|subtitle| subtitle.end_flap as i64)
+        .max()
+        .unwrap_or(0)
+}
+/// 返回字幕冲突(同一帧有多条主字幕或副字幕)的帧数
+///
+/// total_frames小于字幕的结束帧时按字幕补足; 字幕的帧号范围须满足 1 <= 开始 <= 结束
 #[inline]
 pub fn init_render_data(
     render_data: &mut Vec<RenderData>,
     subtitles: &Vec<Subtitle>,
-    input_video: Stream,
-    arg: &InputArg,
-) -> anyhow::Result<()> {
-    render_data.reserve(input_video.frames() as usize);
-    for i in 0..input_video.frames() {
+    total_frames: i64,
+    top_offset: u32,
+    bottom_offset: u32,
+) -> anyhow::Result<usize> {
+    check_subtitles(subtitles)?;
+    let total_frames = subtitle_frames(subtitles).max(total_frames);
+    // 按帧记录冲突, 同一帧的多次冲突 (如主副字幕都冲突) 只计一次
+    let mut conflicted = vec![false; total_frames as usize];
+    render_data.reserve(total_frames as usize);
+    for i in 0..total_frames {
         render_data.push(RenderData {
             flap: i as usize,
             major: None,
             minor: None,
-            bottom_offset: arg.bottom_offset as usize,
-            top_offset: arg.top_offset as usize,
+            bottom_offset: bottom_offset as usize,
+            top_offset: top_offset as usize,
         });
     }
     for subtitle in subtitles.iter() {
@@ -52,7 +68,8 @@ pub fn init_render_data(
                             prev.id,
                             id,
                             flap.flap + 1,
-                        )
+                        );
+                        conflicted[flap.flap] = true;
                     }
                     flap.major = Some(SubtitleWrapper {
                         id: *id as usize,
@@ -67,6 +84,7 @@ pub fn init_render_data(
                             id,
                             flap.flap + 1
                         );
+                        conflicted[flap.flap] = true;
                     }
                     flap.minor = Some(SubtitleWrapper {
                         id: *id as usize,
@@ -77,5 +95,44 @@ pub fn init_render_data(
         }
     }
 
-    return Ok(());
+    return Ok(conflicted.iter().filter(|conflicted| **conflicted).count());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subtitle(begin_flap: u64, end_flap: u64) -> Subtitle {
+        Subtitle {
+            subtitle_type: subtitle::SubtitleType::Major,
+            id: 1,
+            begin_flap,
+            end_flap,
+            data: Arc::new(Video::empty()),
+        }
+    }
+
+    fn render(subtitles: &Vec<Subtitle>, total_frames: i64) -> anyhow::Result<Vec<RenderData>> {
+        let mut render_data = vec![];
+        init_render_data(&mut render_data, subtitles, total_frames, 40, 40)?;
+        return Ok(render_data);
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert!(render(&vec![subtitle(0, 10)], 100).is_err());
+        assert!(render(&vec![subtitle(20, 10)], 100).is_err());
+        assert!(render(&vec![subtitle(10, 10)], 100).is_ok());
+    }
+
+    #[test]
+    fn covers_subtitles_past_reported_length() {
+        // 未记录帧数的输入 (如部分MKV) 总帧数为0
+        let render_data = render(&vec![subtitle(5, 30)], 0).unwrap();
+        assert_eq!(render_data.len(), 30);
+        assert!(render_data[3].major.is_none());
+        assert!(render_data[4].major.is_some());
+        assert!(render_data[29].major.is_some());
+        assert_eq!(render(&vec![subtitle(5, 30)], 50).unwrap().len(), 50);
+    }
 }
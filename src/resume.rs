@@ -0,0 +1,270 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use ffmpeg_next::{
+    codec, encoder,
+    format::{
+        context::{Input, Output},
+        input, output_as,
+    },
+    Error, Packet, Rational,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::cmdline::InputArg;
+
+// 进度记录, 位于分段文件夹中
+const JOURNAL: &str = "journal.json";
+// nut格式保留编码器的time base, 拼接时时间戳不会因取整而错位
+const SEGMENT_FORMAT: &str = "nut";
+
+/// 分段文件夹, 位于输出文件旁
+pub fn parts_dir(output: &str) -> PathBuf {
+    PathBuf::from(format!("{}.parts", output))
+}
+
+/// 用于确认进度记录属于同一任务的参数
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct JobKey {
+    pub input: String,
+    pub subtitle_files: String,
+    pub width: u32,
+    pub height: u32,
+    pub chunk_size: u32,
+    // 其余影响输出的参数, 不同设置编码的分段不能拼接在一起
+    #[serde(default)]
+    pub settings: String,
+}
+
+/// 影响输出内容的参数; 进度显示, 线程数等不影响输出的参数不计入
+pub fn encoding_settings(arg: &InputArg) -> String {
+    format!(
+        "preset={:?} tune={:?} bitrate={:?} bottom_offset={} top_offset={} reference_white={} \
+         video_stream={:?} map={:?} audio_codec={:?} audio_bitrate={:?} loudness={:?} \
+         title={:?} language={:?} subtitle_tag={:?} proxy={:?}",
+        arg.x264_preset,
+        arg.x264_tune,
+        arg.bitrate,
+        arg.bottom_offset,
+        arg.top_offset,
+        arg.reference_white,
+        arg.video_stream,
+        arg.map,
+        arg.audio_codec,
+        arg.audio_bitrate,
+        arg.loudness,
+        arg.title,
+        arg.language,
+        arg.subtitle_tag,
+        arg.proxy,
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+struct SegmentRecord {
+    file: String,
+    // 分段包含的帧号范围 (从1开始)
+    first_frame: i64,
+    last_frame: i64,
+    // 分段中的视频包数
+    packets: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Journal {
+    key: JobKey,
+    segments: Vec<SegmentRecord>,
+}
+
+/// 可恢复压制的分段输出
+///
+/// 每块帧由新打开的编码器编码, 写为以关键帧开始的独立分段;
+/// 分段关闭后才记入进度, 中断时未完成的分段在下次运行时丢弃
+pub struct Segments {
+    dir: PathBuf,
+    journal: Journal,
+    // 当前分段及其中的包数
+    current: Option<(Output, i64)>,
+    // 编码器输出的包的time base
+    time_base: Rational,
+}
+
+impl Segments {
+    /// 读取已有的进度记录, 参数不一致时报错, 避免拼接出错误的成品
+    pub fn open(output: &str, key: JobKey, time_base: Rational) -> anyhow::Result<Self> {
+        let dir = parts_dir(output);
+        let journal_path = dir.join(JOURNAL);
+        let journal = if journal_path.exists() {
+            let content = std::fs::read_to_string(&journal_path)
+                .map_err(|e| anyhow!("Failed to read {}: {}", journal_path.display(), e))?;
+            let journal = serde_json::from_str::<Journal>(&content)
+                .map_err(|e| anyhow!("Invalid journal {}: {}", journal_path.display(), e))?;
+            if journal.key != key {
+                return Err(anyhow!(
+                    "{} belongs to a different job ({:?}), remove it to start over",
+                    dir.display(),
+                    journal.key
+                ));
+            }
+            journal
+        } else {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+            Journal {
+                key,
+                segments: vec![],
+            }
+        };
+        let segments = Self {
+            dir,
+            journal,
+            current: None,
+            time_base,
+        };
+        if segments.frames_done() > 0 {
+            info!(
+                "Resuming after frame {} ({} segments done)",
+                segments.frames_done(),
+                segments.journal.segments.len()
+            );
+        }
+        return Ok(segments);
+    }
+    /// 已完成的帧数
+    pub fn frames_done(&self) -> i64 {
+        self.journal
+            .segments
+            .last()
+            .map_or(0, |segment| segment.last_frame)
+    }
+    /// 已完成的分段中的视频包数
+    pub fn packets_done(&self) -> i64 {
+        self.journal
+            .segments
+            .iter()
+            .map(|segment| segment.packets)
+            .sum()
+    }
+    fn segment_path(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("segment-{:05}.nut", index + 1))
+    }
+    /// 写入编码器输出的包, 当前没有打开的分段时新建
+    pub fn write(
+        &mut self,
+        packet: &mut Packet,
+        video_encoder: &encoder::Video,
+    ) -> anyhow::Result<()> {
+        if self.current.is_none() {
+            let path = self.segment_path(self.journal.segments.len());
+            let mut ctx = output_as(&path, SEGMENT_FORMAT)
+                .map_err(|e| anyhow!("Failed to create segment {}: {}", path.display(), e))?;
+            let mut stream = ctx
+                .add_stream(encoder::find(codec::Id::H264))
+                .map_err(|e| anyhow!("Failed to add stream: {}", e))?;
+            stream.set_parameters(video_encoder);
+            stream.set_time_base(self.time_base);
+            ctx.write_header()
+                .map_err(|e| anyhow!("Failed to write segment header: {}", e))?;
+            self.current = Some((ctx, 0));
+        }
+        let (ctx, packets) = self.current.as_mut().unwrap();
+        let stream_time_base = ctx.stream(0).unwrap().time_base();
+        packet.set_stream(0);
+        packet.rescale_ts(self.time_base, stream_time_base);
+        packet
+            .write_interleaved(ctx)
+            .map_err(|e| anyhow!("Failed to write segment: {}", e))?;
+        *packets += 1;
+        return Ok(());
+    }
+    /// 关闭当前分段并记入进度, 此前须已取出编码器中的全部包
+    pub fn close(&mut self, first_frame: i64, last_frame: i64) -> anyhow::Result<()> {
+        let (mut ctx, packets) = match self.current.take() {
+            Some(current) => current,
+            None => return Ok(()),
+        };
+        ctx.write_trailer()
+            .map_err(|e| anyhow!("Failed to close segment: {}", e))?;
+        let index = self.journal.segments.len();
+        self.journal.segments.push(SegmentRecord {
+            file: self
+                .segment_path(index)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string(),
+            first_frame,
+            last_frame,
+            packets,
+        });
+        // 先写临时文件再改名, 避免中断时进度记录损坏
+        let journal_path = self.dir.join(JOURNAL);
+        let temp_path = self.dir.join(format!("{}.tmp", JOURNAL));
+        let content = serde_json::to_string_pretty(&self.journal)
+            .map_err(|e| anyhow!("Failed to serialize journal: {}", e))?;
+        std::fs::write(&temp_path, content)
+            .and_then(|_| std::fs::rename(&temp_path, &journal_path))
+            .map_err(|e| anyhow!("Failed to write {}: {}", journal_path.display(), e))?;
+        return Ok(());
+    }
+    /// 按顺序读取全部分段中的视频包
+    pub fn reader(&self) -> SegmentReader {
+        SegmentReader {
+            files: self
+                .journal
+                .segments
+                .iter()
+                .map(|segment| self.dir.join(&segment.file))
+                .collect(),
+            next_file: 0,
+            current: None,
+        }
+    }
+    /// 拼接完成后删除分段文件夹
+    pub fn remove(self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            warn!("Failed to remove {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+pub struct SegmentReader {
+    files: Vec<PathBuf>,
+    next_file: usize,
+    current: Option<Input>,
+}
+
+impl SegmentReader {
+    fn open_next(&mut self) -> anyhow::Result<bool> {
+        let path: &Path = match self.files.get(self.next_file) {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        self.current = Some(
+            input(&path)
+                .map_err(|e| anyhow!("Failed to open segment {}: {}", path.display(), e))?,
+        );
+        self.next_file += 1;
+        return Ok(true);
+    }
+    /// 读取下一个视频包, 时间戳转换为time_base
+    pub fn next_packet(&mut self, time_base: Rational) -> anyhow::Result<Option<Packet>> {
+        loop {
+            if self.current.is_none() && !self.open_next()? {
+                return Ok(None);
+            }
+            let ctx = self.current.as_mut().unwrap();
+            let mut packet = Packet::empty();
+            match packet.read(ctx) {
+                Ok(()) => {
+                    let stream_time_base = ctx.stream(packet.stream()).unwrap().time_base();
+                    packet.rescale_ts(stream_time_base, time_base);
+                    return Ok(Some(packet));
+                }
+                Err(Error::Eof) => self.current = None,
+                Err(e) => return Err(anyhow!("Failed to read segment: {}", e)),
+            }
+        }
+    }
+}
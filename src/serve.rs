@@ -0,0 +1,232 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use ffmpeg_next::format::Pixel;
+use log::{debug, info, warn};
+
+use crate::{
+    cmdline::{InputArg, ServeArg},
+    contact_sheet::escape_html,
+    image::encode_png,
+    preview::compose,
+    reader::FrameReader,
+    render::{init_render_data, subtitle_frames, RenderData},
+    subtitle::{load_subtitles, Subtitle, SubtitleType},
+};
+
+// 连接的读写超时; 请求按顺序处理, 浏览器预连接等空闲连接不能一直占用服务
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 浏览页面, $TITLE$ 等占位符在启动时替换
+const PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>$TITLE$</title>
+<style>
+body{font-family:sans-serif;margin:8px}
+#view{max-width:100%;display:block;margin:8px 0}
+#slider{width:100%}
+table{border-collapse:collapse}
+td{padding:2px 8px;border-bottom:1px solid #ddd}
+</style></head><body>
+<input type="range" id="slider" min="1" max="$TOTAL$" value="1">
+<div>
+<button onclick="show(+frame.value-1)">&lt;</button>
+<input type="number" id="frame" min="1" max="$TOTAL$" value="1">
+<button onclick="show(+frame.value+1)">&gt;</button>
+<span id="time"></span>
+</div>
+<img id="view">
+<table><tr><th>id</th><th>track</th><th>frames</th></tr>
+$ROWS$</table>
+<script>
+const fps = $FPS$;
+function show(n) {
+    n = Math.max(1, Math.min($TOTAL$, Math.round(n)));
+    slider.value = n;
+    frame.value = n;
+    time.textContent = ((n - 1) / fps).toFixed(3) + "s";
+    view.src = "/frame?n=" + n;
+}
+slider.onchange = () => show(+slider.value);
+frame.onchange = () => show(+frame.value);
+show(1);
+</script>
+</body></html>
+"#;
+
+fn build_page(title: &str, subtitles: &[Subtitle], total_frames: i64, fps: f64) -> String {
+    let mut rows = String::new();
+    for subtitle in subtitles.iter() {
+        let track = match subtitle.subtitle_type {
+            SubtitleType::Major => "major",
+            SubtitleType::Minor => "minor",
+        };
+        rows.push_str(&format!(
+            "<tr><td><a href=\"#\" onclick=\"show({});return false\">#{}</a></td><td>{}</td><td>{}-{}</td></tr>\n",
+            subtitle.begin_flap, subtitle.id, track, subtitle.begin_flap, subtitle.end_flap
+        ));
+    }
+    return PAGE
+        .replace("$TITLE$", &escape_html(title))
+        .replace("$TOTAL$", &total_frames.to_string())
+        .replace("$FPS$", &fps.to_string())
+        .replace("$ROWS$", &rows);
+}
+
+pub(crate) fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    return stream.flush();
+}
+
+/// 从 `/frame?n=100` 或 `/frame?t=12.5` 中解析帧号
+fn parse_frame_query(query: &str, fps: f64) -> anyhow::Result<i64> {
+    for pair in query.split('&') {
+        match pair.split_once('=') {
+            Some(("n", value)) => {
+                return value
+                    .parse::<i64>()
+                    .map_err(|e| anyhow!("Invalid frame {}: {}", value, e));
+            }
+            Some(("t", value)) => {
+                let time = value
+                    .parse::<f64>()
+                    .map_err(|e| anyhow!("Invalid time {}: {}", value, e))?;
+                return Ok((time * fps).floor() as i64 + 1);
+            }
+            _ => {}
+        }
+    }
+    return Err(anyhow!("Missing frame number (n) or time (t)"));
+}
+
+struct Server<'a> {
+    arg: &'a InputArg,
+    reader: FrameReader,
+    render_data: Vec<RenderData>,
+    page: String,
+    fps: f64,
+}
+
+impl<'a> Server<'a> {
+    fn handle(&mut self, stream: &mut TcpStream) -> anyhow::Result<()> {
+        let mut request_line = String::new();
+        let mut request = BufReader::new(stream.try_clone()?);
+        request.read_line(&mut request_line)?;
+        // 忽略请求头
+        let mut header = String::new();
+        while request.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+        debug!("Request: {}", request_line.trim());
+        let mut parts = request_line.split_whitespace();
+        let (status, content_type, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some(target)) => {
+                let (path, query) = target.split_once('?').unwrap_or((target, ""));
+                match path {
+                    "/" => (
+                        "200 OK",
+                        "text/html; charset=utf-8",
+                        self.page.clone().into_bytes(),
+                    ),
+                    "/frame" => match self.render_frame(query) {
+                        Ok(png) => ("200 OK", "image/png", png),
+                        Err(e) => {
+                            warn!("Failed to render {}: {}", target, e);
+                            (
+                                "404 Not Found",
+                                "text/plain; charset=utf-8",
+                                e.to_string().into_bytes(),
+                            )
+                        }
+                    },
+                    _ => ("404 Not Found", "text/plain", vec![]),
+                }
+            }
+            (Some(_), Some(_)) => ("405 Method Not Allowed", "text/plain", vec![]),
+            _ => ("400 Bad Request", "text/plain", vec![]),
+        };
+        respond(stream, status, content_type, &body)?;
+        return Ok(());
+    }
+    fn render_frame(&mut self, query: &str) -> anyhow::Result<Vec<u8>> {
+        let frame_number = parse_frame_query(query, self.fps)?;
+        if frame_number < 1 {
+            return Err(anyhow!("Frame number starts from 1"));
+        }
+        let frame = self.reader.read_frame(frame_number)?;
+        let image = compose(&self.render_data, frame, frame_number, self.arg)?;
+        return encode_png(&image);
+    }
+}
+
+/// 启动本地HTTP服务, 在浏览器中逐帧查看字幕合成效果
+pub fn run(arg: &InputArg, serve_arg: &ServeArg) -> anyhow::Result<()> {
+    let input_name = arg
+        .input
+        .first()
+        .ok_or(anyhow!("No input file specified"))?;
+    let reader = FrameReader::open(input_name, arg.video_stream, Pixel::RGB24, None)?;
+    let fps = f64::from(reader.frame_rate());
+    let mut subtitles = load_subtitles(&PathBuf::from(&arg.subtitle_files))
+        .map_err(|e| anyhow!("Failed to read subtitles: {}\n", e))?;
+    info!("{} subtitles loaded.", subtitles.len());
+    let total_frames = subtitle_frames(&subtitles).max(reader.frames());
+    let mut render_data = Vec::<RenderData>::new();
+    init_render_data(
+        &mut render_data,
+        &subtitles,
+        total_frames,
+        arg.top_offset,
+        arg.bottom_offset,
+    )?;
+    subtitles.sort_by_key(|subtitle| (subtitle.begin_flap, subtitle.id));
+    let page = build_page(input_name, &subtitles, total_frames, fps);
+
+    let listener = TcpListener::bind(&serve_arg.listen)
+        .map_err(|e| anyhow!("Failed to listen on {}: {}", serve_arg.listen, e))?;
+    info!("Review server started at http://{}/", serve_arg.listen);
+    let mut server = Server {
+        arg,
+        reader,
+        render_data,
+        page,
+        fps,
+    };
+    // 解码器只有一个, 按顺序处理请求
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT)))
+        {
+            warn!("Failed to set connection timeout: {}", e);
+            continue;
+        }
+        if let Err(e) = server.handle(&mut stream) {
+            warn!("Failed to handle request: {}", e);
+        }
+    }
+    return Ok(());
+}
@@ -1,19 +1,27 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use anyhow::anyhow;
 use ffmpeg_next::frame::Video;
 use log::{debug, error, info};
 use regex::Regex;
 
-use crate::image::read_image;
+use crate::{
+    archive::{is_archive, ArchiveSource},
+    image::{decode_png, read_image},
+};
 
 lazy_static::lazy_static! {
-    static ref FILENAME_EXPR:Regex = Regex::new(r#"(?P<type>(major)|(minor))-subtitle-(?P<id>[0-9]+)-(?P<begin>[0-9]+)-(?P<end>[0-9]+)\.png"#).unwrap();
+    static ref FILENAME_EXPR:Regex = Regex::new(r#"^(?P<type>(major)|(minor))-subtitle-(?P<id>[0-9]+)-(?P<begin>[0-9]+)-(?P<end>[0-9]+)\.png$"#).unwrap();
 }
+#[derive(Clone, Copy)]
 pub enum SubtitleType {
     Major,
     Minor,
 }
+#[derive(Clone)]
 pub struct Subtitle {
     pub subtitle_type: SubtitleType,
     pub id: u64,
@@ -22,63 +30,207 @@ pub struct Subtitle {
     pub data: Arc<Video>,
 }
 
-pub fn load_subtitles(root: &Path) -> anyhow::Result<Vec<Subtitle>> {
-    let mut subtitles = vec![];
-    for file in std::fs::read_dir(root)
-        .map_err(|e| anyhow!("Failed to read directory: {}", e))?
-        .into_iter()
-    {
-        match file {
-            Ok(file) => {
-                let path = file.path();
-                let filename = path.file_name().unwrap().to_str().unwrap();
-                debug!("Reading: {}", filename);
-                if let Some(groups) = FILENAME_EXPR.captures(filename) {
-                    let subtitle_type = match groups.name("type").unwrap().as_str() {
-                        "major" => SubtitleType::Major,
-                        "minor" => SubtitleType::Minor,
-                        _ => {
-                            return Err(anyhow!(
-                                "Invalid subtitle type: {}",
-                                groups.name("type").unwrap().as_str()
-                            ));
+impl Subtitle {
+    /// 由符合文件名规则的字幕图片构造, 文件名不符合规则时返回None
+    pub fn from_file_name(
+        filename: &str,
+        read: impl FnOnce() -> anyhow::Result<Video>,
+    ) -> anyhow::Result<Option<Self>> {
+        let groups = match FILENAME_EXPR.captures(filename) {
+            Some(groups) => groups,
+            None => return Ok(None),
+        };
+        let subtitle_type = match groups.name("type").unwrap().as_str() {
+            "major" => SubtitleType::Major,
+            "minor" => SubtitleType::Minor,
+            _ => {
+                return Err(anyhow!(
+                    "Invalid subtitle type: {}",
+                    groups.name("type").unwrap().as_str()
+                ));
+            }
+        };
+        let id = groups.name("id").unwrap().as_str().parse::<u64>().unwrap();
+        let begin_flap = groups
+            .name("begin")
+            .unwrap()
+            .as_str()
+            .parse::<u64>()
+            .map_err(|e| anyhow!("Invalid begin flap number for file {}, {}", filename, e))?;
+        let end_flap = groups
+            .name("end")
+            .unwrap()
+            .as_str()
+            .parse::<u64>()
+            .map_err(|e| anyhow!("Invalid end flap number for file {}, {}", filename, e))?;
+        let data = read().map_err(|e| anyhow!("Failed to read image: {}", e))?;
+        return Ok(Some(Subtitle {
+            subtitle_type,
+            id,
+            begin_flap,
+            end_flap,
+            data: Arc::new(data),
+        }));
+    }
+}
+
+/// 字幕的来源; 作为库使用时可自行实现, 如由文字渲染字幕
+pub trait SubtitleSource: Send {
+    /// 载入全部字幕, 另外返回被忽略的条目数
+    fn load(&mut self) -> anyhow::Result<(Vec<Subtitle>, usize)>;
+}
+
+/// 字幕图片文件夹, 读取其中符合文件名规则的图片
+pub struct DirSource {
+    root: PathBuf,
+}
+
+impl DirSource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl SubtitleSource for DirSource {
+    fn load(&mut self) -> anyhow::Result<(Vec<Subtitle>, usize)> {
+        let mut subtitles = vec![];
+        let mut ignored = 0;
+        for file in std::fs::read_dir(&self.root)
+            .map_err(|e| anyhow!("Failed to read directory: {}", e))?
+            .into_iter()
+        {
+            match file {
+                Ok(file) => {
+                    let path = file.path();
+                    let filename = path.file_name().unwrap().to_str().unwrap();
+                    debug!("Reading: {}", filename);
+                    match Subtitle::from_file_name(filename, || read_image(&path))? {
+                        Some(subtitle) => subtitles.push(subtitle),
+                        None => {
+                            info!("Ignoring file: {}", filename);
+                            ignored += 1;
                         }
-                    };
-                    let id = groups.name("id").unwrap().as_str().parse::<u64>().unwrap();
-                    let begin_flap = groups
-                        .name("begin")
-                        .unwrap()
-                        .as_str()
-                        .parse::<u64>()
-                        .map_err(|e| {
-                            anyhow!("Invalid begin flap number for file {}, {}", filename, e)
-                        })?;
-                    let end_flap = groups
-                        .name("end")
-                        .unwrap()
-                        .as_str()
-                        .parse::<u64>()
-                        .map_err(|e| {
-                            anyhow!("Invalid end flap number for file {}, {}", filename, e)
-                        })?;
-                    let data =
-                        read_image(&path).map_err(|e| anyhow!("Failed to read image: {}", e))?;
-                    let subtitle = Subtitle {
-                        subtitle_type,
-                        id,
-                        begin_flap,
-                        end_flap,
-                        data: Arc::new(data),
-                    };
-                    subtitles.push(subtitle);
-                } else {
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to read :{}, ignoring", e);
+                    ignored += 1;
+                }
+            };
+        }
+        return Ok((subtitles, ignored));
+    }
+}
+
+/// 内存中的PNG字幕图片, 文件名规则与字幕文件夹中的相同
+#[derive(Default)]
+pub struct MemorySource {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add(&mut self, filename: impl Into<String>, png: impl Into<Vec<u8>>) {
+        self.files.push((filename.into(), png.into()));
+    }
+}
+
+impl SubtitleSource for MemorySource {
+    fn load(&mut self) -> anyhow::Result<(Vec<Subtitle>, usize)> {
+        let mut subtitles = vec![];
+        let mut ignored = 0;
+        for (filename, png) in self.files.iter() {
+            debug!("Decoding: {}", filename);
+            match Subtitle::from_file_name(filename, || decode_png(png))? {
+                Some(subtitle) => subtitles.push(subtitle),
+                None => {
                     info!("Ignoring file: {}", filename);
+                    ignored += 1;
                 }
             }
-            Err(e) => {
-                error!("Failed to read :{}, ignoring", e);
-            }
-        };
+        }
+        return Ok((subtitles, ignored));
+    }
+}
+
+/// 已构造好的字幕
+impl SubtitleSource for Vec<Subtitle> {
+    fn load(&mut self) -> anyhow::Result<(Vec<Subtitle>, usize)> {
+        return Ok((self.clone(), 0));
+    }
+}
+
+/// 按路径选择字幕来源: 压缩包或字幕图片文件夹
+pub fn open_source(path: &Path) -> Box<dyn SubtitleSource> {
+    if is_archive(path) {
+        return Box::new(ArchiveSource::new(path));
+    }
+    return Box::new(DirSource::new(path));
+}
+
+/// 检查字幕的帧号范围, 须满足 1 <= 开始 <= 结束
+pub fn check_subtitles(subtitles: &[Subtitle]) -> anyhow::Result<()> {
+    for subtitle in subtitles.iter() {
+        if subtitle.begin_flap == 0 || subtitle.begin_flap > subtitle.end_flap {
+            return Err(anyhow!(
+                "Invalid frame range of subtitle {}: {}-{}, frames are numbered from 1",
+                subtitle.id,
+                subtitle.begin_flap,
+                subtitle.end_flap
+            ));
+        }
+    }
+    return Ok(());
+}
+
+/// 从来源载入字幕并检查帧号范围, 各种来源均经此载入
+pub fn load_source(source: &mut dyn SubtitleSource) -> anyhow::Result<(Vec<Subtitle>, usize)> {
+    let (subtitles, ignored) = source.load()?;
+    check_subtitles(&subtitles)?;
+    return Ok((subtitles, ignored));
+}
+
+pub fn load_subtitles(root: &Path) -> anyhow::Result<Vec<Subtitle>> {
+    return Ok(load_subtitles_counted(root)?.0);
+}
+
+/// 同load_subtitles, 另外返回被忽略的文件数
+pub fn load_subtitles_counted(root: &Path) -> anyhow::Result<(Vec<Subtitle>, usize)> {
+    return load_source(open_source(root).as_mut());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_name_must_match_whole_name() {
+        for name in [
+            "._major-subtitle-1-1-10.png",
+            "major-subtitle-1-1-10.png.bak",
+            "old-minor-subtitle-2-5-9.png",
+        ] {
+            let subtitle = Subtitle::from_file_name(name, || panic!("{} should not be read", name));
+            assert!(subtitle.unwrap().is_none());
+        }
+    }
+
+    fn subtitle(begin_flap: u64, end_flap: u64) -> Subtitle {
+        Subtitle {
+            subtitle_type: SubtitleType::Minor,
+            id: 7,
+            begin_flap,
+            end_flap,
+            data: Arc::new(Video::empty()),
+        }
+    }
+
+    #[test]
+    fn load_source_checks_frame_ranges() {
+        assert!(load_source(&mut vec![subtitle(1, 1), subtitle(3, 9)]).is_ok());
+        assert!(load_source(&mut vec![subtitle(1, 1), subtitle(0, 9)]).is_err());
+        assert!(load_source(&mut vec![subtitle(10, 9)]).is_err());
     }
-    return Ok(subtitles);
 }
@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use serde::Serialize;
+
+use crate::progress::{Progress, Stage};
+
+#[derive(Serialize)]
+pub struct InputSummary {
+    pub file: String,
+    pub duration: Option<f64>,
+    pub frames: i64,
+}
+
+#[derive(Serialize)]
+pub struct VideoSummary {
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: String,
+    pub fps: f64,
+}
+
+#[derive(Serialize)]
+pub struct OutputSummary {
+    pub file: String,
+    pub video: VideoSummary,
+    // 文件大小 (字节)
+    pub size: Option<u64>,
+    pub duration: f64,
+    // 按文件大小与时长计算的平均码率 (bit/s)
+    pub bitrate: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct EncoderSummary {
+    pub codec: String,
+    pub preset: String,
+    pub pixel_format: String,
+    pub bitrate: usize,
+    pub threads: u32,
+    pub proxy_height: Option<u32>,
+    pub audio_transcoded_streams: usize,
+}
+
+#[derive(Serialize)]
+pub struct SubtitleSummary {
+    pub loaded: usize,
+    pub ignored: usize,
+    // 字幕冲突的帧数
+    pub conflicting_frames: usize,
+}
+
+#[derive(Serialize)]
+pub struct FrameSummary {
+    pub decoded: i64,
+    pub kept: i64,
+    // 被剪辑掉的帧
+    pub trimmed: i64,
+    // 无字幕, 直接送入编码器的帧
+    pub bypassed: i64,
+    pub encoded: i64,
+}
+
+#[derive(Serialize)]
+pub struct StageSummary {
+    pub stage: &'static str,
+    pub secs: f64,
+    // 视频时长 / 耗时
+    pub speed: f64,
+}
+
+impl StageSummary {
+    pub fn from_progress(progress: &Progress) -> Vec<Self> {
+        Stage::ALL
+            .iter()
+            .map(|&stage| {
+                let (secs, video_secs) = progress.stage_total(stage);
+                StageSummary {
+                    stage: stage.name(),
+                    secs,
+                    speed: if secs > 0.0 { video_secs / secs } else { 0.0 },
+                }
+            })
+            .collect()
+    }
+}
+
+/// 一次压制的汇总报告, 以JSON格式与成品一同归档
+#[derive(Serialize)]
+pub struct Summary {
+    pub version: &'static str,
+    pub inputs: Vec<InputSummary>,
+    pub source: VideoSummary,
+    pub output: OutputSummary,
+    pub encoder: EncoderSummary,
+    pub subtitles: SubtitleSummary,
+    pub frames: FrameSummary,
+    pub stages: Vec<StageSummary>,
+    pub elapsed: f64,
+}
+
+impl Summary {
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)
+            .map_err(|e| anyhow!("Failed to create summary {}: {}", path.display(), e))?;
+        serde_json::to_writer_pretty(file, self)
+            .map_err(|e| anyhow!("Failed to write summary {}: {}", path.display(), e))?;
+        return Ok(());
+    }
+}
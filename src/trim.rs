@@ -0,0 +1,332 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use ffmpeg_next::{Packet, Rational};
+use log::info;
+
+/// 解析 `SS[.ms]`, `MM:SS[.ms]` 或 `HH:MM:SS[.ms]` 格式的时间, 单位为秒
+pub fn parse_time(s: &str) -> Result<f64, String> {
+    let mut result = 0.0;
+    for part in s.split(':') {
+        let value = part
+            .parse::<f64>()
+            .map_err(|e| format!("Invalid time {}: {}", s, e))?;
+        // f64的解析接受nan与inf
+        if !value.is_finite() {
+            return Err(format!("Invalid time: {}", s));
+        }
+        result = result * 60.0 + value;
+    }
+    if result < 0.0 {
+        return Err(format!("Negative time: {}", s));
+    }
+    return Ok(result);
+}
+
+/// 保留的时间段 [start, end), 形如 `10-20.5`, `01:00-01:30:00`, `300-`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeRange {
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+impl FromStr for TimeRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or(format!("Invalid time range: {}", s))?;
+        let start = if start.is_empty() {
+            0.0
+        } else {
+            parse_time(start)?
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(parse_time(end)?)
+        };
+        if end.map_or(false, |end| end <= start) {
+            return Err(format!("Empty time range: {}", s));
+        }
+        return Ok(Self { start, end });
+    }
+}
+
+/// 剪辑后的时间轴, 时间均相对于输入文件的起始时间
+pub struct Trim {
+    ranges: Vec<TimeRange>,
+    // 输入文件的起始时间(秒)
+    origin: f64,
+}
+
+impl Trim {
+    pub fn new(
+        start: Option<f64>,
+        end: Option<f64>,
+        keep: &[TimeRange],
+        origin: f64,
+    ) -> anyhow::Result<Option<Self>> {
+        let mut ranges = keep.to_vec();
+        if start.is_some() || end.is_some() {
+            if !ranges.is_empty() {
+                return Err(anyhow!(
+                    "--start/--end can not be used together with --keep"
+                ));
+            }
+            let start = start.unwrap_or(0.0);
+            if end.map_or(false, |end| end <= start) {
+                return Err(anyhow!("--end must be greater than --start"));
+            }
+            ranges.push(TimeRange { start, end });
+        }
+        if ranges.is_empty() {
+            return Ok(None);
+        }
+        ranges.sort_by(|a, b| a.start.total_cmp(&b.start));
+        for pair in ranges.windows(2) {
+            if pair[0].end.map_or(true, |end| end > pair[1].start) {
+                return Err(anyhow!(
+                    "Overlapping time ranges: {:?} and {:?}",
+                    pair[0],
+                    pair[1]
+                ));
+            }
+        }
+        info!("Keeping time ranges: {:?}", ranges);
+        return Ok(Some(Self { ranges, origin }));
+    }
+    /// 若时间t(秒)被保留, 返回t之前被剪掉的总时长
+    pub fn removed_before(&self, t: f64) -> Option<f64> {
+        let t = t - self.origin;
+        let mut removed = 0.0;
+        let mut prev_end = 0.0;
+        for range in self.ranges.iter() {
+            if t < range.start {
+                return None;
+            }
+            removed += range.start - prev_end;
+            match range.end {
+                Some(end) if t >= end => prev_end = end,
+                _ => return Some(removed),
+            }
+        }
+        return None;
+    }
+    /// 将时间戳映射到剪辑后的时间轴, 被剪掉时返回None
+    pub fn shift_ts(&self, ts: i64, time_base: Rational) -> Option<i64> {
+        let removed = self.removed_before(ts as f64 * f64::from(time_base))?;
+        return Some(ts - (removed / f64::from(time_base)).round() as i64);
+    }
+    /// 平移数据包的时间戳, 包被剪掉时返回false
+    pub fn shift_packet(&self, packet: &mut Packet, time_base: Rational) -> bool {
+        let ts = match packet.pts().or(packet.dts()) {
+            Some(ts) => ts,
+            None => return true,
+        };
+        let removed = match self.removed_before(ts as f64 * f64::from(time_base)) {
+            Some(removed) => (removed / f64::from(time_base)).round() as i64,
+            None => return false,
+        };
+        packet.set_pts(packet.pts().map(|v| v - removed));
+        packet.set_dts(packet.dts().map(|v| v - removed));
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_formats() {
+        assert_eq!(parse_time("90"), Ok(90.0));
+        assert_eq!(parse_time("01:30.5"), Ok(90.5));
+        assert_eq!(parse_time("1:00:00"), Ok(3600.0));
+    }
+
+    #[test]
+    fn parse_time_rejects_non_finite() {
+        for s in ["nan", "inf", "-inf", "1:nan", "infinity:00"] {
+            assert!(parse_time(s).is_err(), "{} should be rejected", s);
+        }
+        assert!("nan-10".parse::<TimeRange>().is_err());
+    }
+
+    fn keep(ranges: &[&str], origin: f64) -> Trim {
+        let ranges = ranges
+            .iter()
+            .map(|range| range.parse::<TimeRange>().unwrap())
+            .collect::<Vec<TimeRange>>();
+        Trim::new(None, None, &ranges, origin).unwrap().unwrap()
+    }
+
+    fn packet(pts: Option<i64>, dts: Option<i64>) -> Packet {
+        let mut packet = Packet::empty();
+        packet.set_pts(pts);
+        packet.set_dts(dts);
+        packet
+    }
+
+    #[test]
+    fn parse_time_range() {
+        assert_eq!(
+            "10-20.5".parse::<TimeRange>(),
+            Ok(TimeRange {
+                start: 10.0,
+                end: Some(20.5)
+            })
+        );
+        assert_eq!(
+            "300-".parse::<TimeRange>(),
+            Ok(TimeRange {
+                start: 300.0,
+                end: None
+            })
+        );
+        assert_eq!(
+            "-01:00".parse::<TimeRange>(),
+            Ok(TimeRange {
+                start: 0.0,
+                end: Some(60.0)
+            })
+        );
+        assert!("20-10".parse::<TimeRange>().is_err());
+        assert!("10-10".parse::<TimeRange>().is_err());
+        assert!("10".parse::<TimeRange>().is_err());
+    }
+
+    #[test]
+    fn new_validates_ranges() {
+        let range = |start, end| TimeRange { start, end };
+        assert!(Trim::new(None, None, &[], 0.0).unwrap().is_none());
+        // 相邻的时间段可以同时保留
+        assert!(Trim::new(
+            None,
+            None,
+            &[range(10.0, Some(20.0)), range(20.0, None)],
+            0.0
+        )
+        .is_ok());
+        assert!(Trim::new(
+            None,
+            None,
+            &[range(10.0, Some(20.0)), range(15.0, Some(30.0))],
+            0.0
+        )
+        .is_err());
+        // 不限结束时间的时间段只能在最后
+        assert!(Trim::new(
+            None,
+            None,
+            &[range(10.0, None), range(20.0, Some(30.0))],
+            0.0
+        )
+        .is_err());
+        assert!(Trim::new(Some(10.0), None, &[range(20.0, None)], 0.0).is_err());
+        assert!(Trim::new(Some(10.0), Some(10.0), &[], 0.0).is_err());
+        assert!(Trim::new(Some(10.0), None, &[], 0.0).unwrap().is_some());
+    }
+
+    #[test]
+    fn removed_before_several_ranges() {
+        // 输入顺序不影响结果
+        let trim = keep(&["20-30", "0-10", "40-"], 0.0);
+        assert_eq!(trim.removed_before(0.0), Some(0.0));
+        assert_eq!(trim.removed_before(5.0), Some(0.0));
+        assert_eq!(trim.removed_before(10.0), None);
+        assert_eq!(trim.removed_before(15.0), None);
+        assert_eq!(trim.removed_before(20.0), Some(10.0));
+        assert_eq!(trim.removed_before(29.5), Some(10.0));
+        assert_eq!(trim.removed_before(30.0), None);
+        assert_eq!(trim.removed_before(40.0), Some(20.0));
+        // 最后一段不限结束时间
+        assert_eq!(trim.removed_before(3600.0), Some(20.0));
+    }
+
+    #[test]
+    fn removed_before_adjacent_ranges() {
+        let trim = keep(&["10-20", "20-30"], 0.0);
+        assert_eq!(trim.removed_before(9.5), None);
+        assert_eq!(trim.removed_before(10.0), Some(10.0));
+        assert_eq!(trim.removed_before(20.0), Some(10.0));
+        assert_eq!(trim.removed_before(25.0), Some(10.0));
+        assert_eq!(trim.removed_before(30.0), None);
+    }
+
+    #[test]
+    fn removed_before_open_ended() {
+        let trim = keep(&["300-"], 0.0);
+        assert_eq!(trim.removed_before(299.0), None);
+        assert_eq!(trim.removed_before(300.0), Some(300.0));
+        assert_eq!(trim.removed_before(7200.0), Some(300.0));
+    }
+
+    #[test]
+    fn removed_before_with_start_time() {
+        // 输入文件的起始时间为1.5秒 (如MPEG-TS), 时间段相对于起始时间
+        let trim = keep(&["10-20"], 1.5);
+        assert_eq!(trim.removed_before(11.0), None);
+        assert_eq!(trim.removed_before(11.5), Some(10.0));
+        assert_eq!(trim.removed_before(21.0), Some(10.0));
+        assert_eq!(trim.removed_before(21.5), None);
+    }
+
+    #[test]
+    fn shift_ts_in_time_base() {
+        let trim = keep(&["10-20", "30-40"], 0.0);
+        let ms = Rational::new(1, 1000);
+        assert_eq!(trim.shift_ts(15_000, ms), Some(5_000));
+        assert_eq!(trim.shift_ts(35_000, ms), Some(15_000));
+        assert_eq!(trim.shift_ts(25_000, ms), None);
+        let ts = Rational::new(1, 90000);
+        assert_eq!(trim.shift_ts(990_000, ts), Some(90_000));
+        assert_eq!(
+            trim.shift_ts(2_700_000 + 45_000, ts),
+            Some(900_000 + 45_000)
+        );
+
+        let trim = keep(&["10-20"], 1.5);
+        // 只减去被剪掉的时长, 起始时间保持不变
+        assert_eq!(trim.shift_ts(12_000, ms), Some(2_000));
+    }
+
+    #[test]
+    fn shift_packet_straddling_cuts() {
+        let trim = keep(&["10-20"], 0.0);
+        let ms = Rational::new(1, 1000);
+        // 起始于保留段之前的包被丢弃, 即使其时长跨入保留段
+        let mut before = packet(Some(9_990), Some(9_990));
+        before.set_duration(40);
+        assert!(!trim.shift_packet(&mut before, ms));
+        // 起始于保留段内的包整体保留, 即使其时长跨出保留段
+        let mut across = packet(Some(19_990), Some(19_990));
+        across.set_duration(40);
+        assert!(trim.shift_packet(&mut across, ms));
+        assert_eq!(across.pts(), Some(9_990));
+        assert_eq!(across.dts(), Some(9_990));
+        assert_eq!(across.duration(), 40);
+        assert!(!trim.shift_packet(&mut packet(Some(20_000), Some(20_000)), ms));
+    }
+
+    #[test]
+    fn shift_packet_timestamps() {
+        let trim = keep(&["10-20"], 0.0);
+        let ms = Rational::new(1, 1000);
+        // 按pts判断是否保留, pts与dts平移相同的量
+        let mut reordered = packet(Some(10_080), Some(9_960));
+        assert!(trim.shift_packet(&mut reordered, ms));
+        assert_eq!(reordered.pts(), Some(80));
+        assert_eq!(reordered.dts(), Some(-40));
+        // 没有pts时按dts判断
+        let mut dts_only = packet(None, Some(12_000));
+        assert!(trim.shift_packet(&mut dts_only, ms));
+        assert_eq!(dts_only.pts(), None);
+        assert_eq!(dts_only.dts(), Some(2_000));
+        // 没有时间戳的包原样保留
+        let mut untimed = packet(None, None);
+        assert!(trim.shift_packet(&mut untimed, ms));
+        assert_eq!(untimed.pts(), None);
+    }
+}
@@ -0,0 +1,353 @@
+use std::{io::Write, path::PathBuf};
+
+use anyhow::anyhow;
+use ffmpeg_next::{format::Pixel, frame::Video};
+use log::{info, warn};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    cmdline::{InputArg, VerifyArg},
+    embedder::{placement, Offset},
+    reader::FrameReader,
+    render::{init_render_data, subtitle_frames, RenderData},
+    subtitle::load_subtitles,
+};
+
+// 字幕区域向外扩展的像素数, 用于排除色度下采样与缩放造成的边缘渗色
+const REGION_MARGIN: i32 = 4;
+// SSIM的窗口大小
+const SSIM_BLOCK: usize = 8;
+
+/// 帧中的矩形区域 [top, bottom) x [left, right)
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    top: i32,
+    left: i32,
+    bottom: i32,
+    right: i32,
+}
+
+impl Rect {
+    #[inline]
+    fn contains(&self, row: i32, col: i32) -> bool {
+        row >= self.top && row < self.bottom && col >= self.left && col < self.right
+    }
+    #[inline]
+    fn intersects(&self, top: i32, left: i32, bottom: i32, right: i32) -> bool {
+        self.top < bottom && top < self.bottom && self.left < right && left < self.right
+    }
+}
+
+/// 某帧中字幕所覆盖的区域
+fn subtitle_regions(
+    render_data: Option<&RenderData>,
+    width: i32,
+    height: i32,
+    top_offset: u32,
+    bottom_offset: u32,
+) -> Vec<Rect> {
+    let render_data = match render_data {
+        Some(v) => v,
+        None => return vec![],
+    };
+    let mut result = vec![];
+    for (subtitle, offset) in [
+        (&render_data.major, Offset::MajorBottom(bottom_offset)),
+        (&render_data.minor, Offset::MinorTop(top_offset)),
+    ] {
+        if let Some(subtitle) = subtitle {
+            let (colc, rowc) = (
+                subtitle.image.width() as i32,
+                subtitle.image.height() as i32,
+            );
+            let (lurow, lucol) = placement(width, height, colc, rowc, &offset);
+            result.push(Rect {
+                top: (lurow - REGION_MARGIN).max(0),
+                left: (lucol - REGION_MARGIN).max(0),
+                bottom: (lurow + rowc + REGION_MARGIN).min(height),
+                right: (lucol + colc + REGION_MARGIN).min(width),
+            });
+        }
+    }
+    return result;
+}
+
+/// 字幕区域以外的PSNR (dB), 两帧完全相同时为无穷大
+fn masked_psnr(input: &Video, output: &Video, regions: &[Rect]) -> f64 {
+    let (width, height) = (input.width() as i32, input.height() as i32);
+    let (in_stride, out_stride) = (input.stride(0), output.stride(0));
+    let (in_data, out_data) = (input.data(0), output.data(0));
+    let (sum, count) = (0..height)
+        .into_par_iter()
+        .map(|r| {
+            let mut sum = 0u64;
+            let mut count = 0u64;
+            let in_row = &in_data[r as usize * in_stride..];
+            let out_row = &out_data[r as usize * out_stride..];
+            for c in 0..width {
+                if regions.iter().any(|rect| rect.contains(r, c)) {
+                    continue;
+                }
+                for x in 0..3 {
+                    let idx = c as usize * 3 + x;
+                    let diff = in_row[idx] as i64 - out_row[idx] as i64;
+                    sum += (diff * diff) as u64;
+                }
+                count += 3;
+            }
+            (sum, count)
+        })
+        .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+    if count == 0 || sum == 0 {
+        return f64::INFINITY;
+    }
+    let mse = sum as f64 / count as f64;
+    return 10.0 * (255.0 * 255.0 / mse).log10();
+}
+
+#[inline]
+fn luma(data: &[u8], stride: usize, row: usize, col: usize) -> f64 {
+    let base = row * stride + col * 3;
+    0.299 * data[base] as f64 + 0.587 * data[base + 1] as f64 + 0.114 * data[base + 2] as f64
+}
+
+/// 字幕区域以外的平均SSIM (亮度, 8x8不重叠窗口)
+fn masked_ssim(input: &Video, output: &Video, regions: &[Rect]) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let (in_stride, out_stride) = (input.stride(0), output.stride(0));
+    let (in_data, out_data) = (input.data(0), output.data(0));
+    let n = (SSIM_BLOCK * SSIM_BLOCK) as f64;
+    let (sum, count) = (0..height / SSIM_BLOCK)
+        .into_par_iter()
+        .map(|block_row| {
+            let mut sum = 0.0;
+            let mut count = 0usize;
+            let top = block_row * SSIM_BLOCK;
+            for left in (0..width - width % SSIM_BLOCK).step_by(SSIM_BLOCK) {
+                if regions.iter().any(|rect| {
+                    rect.intersects(
+                        top as i32,
+                        left as i32,
+                        (top + SSIM_BLOCK) as i32,
+                        (left + SSIM_BLOCK) as i32,
+                    )
+                }) {
+                    continue;
+                }
+                let (mut sx, mut sy, mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+                for r in top..top + SSIM_BLOCK {
+                    for c in left..left + SSIM_BLOCK {
+                        let x = luma(in_data, in_stride, r, c);
+                        let y = luma(out_data, out_stride, r, c);
+                        sx += x;
+                        sy += y;
+                        sxx += x * x;
+                        syy += y * y;
+                        sxy += x * y;
+                    }
+                }
+                let (mx, my) = (sx / n, sy / n);
+                let vx = sxx / n - mx * mx;
+                let vy = syy / n - my * my;
+                let cov = sxy / n - mx * my;
+                sum += ((2.0 * mx * my + C1) * (2.0 * cov + C2))
+                    / ((mx * mx + my * my + C1) * (vx + vy + C2));
+                count += 1;
+            }
+            (sum, count)
+        })
+        .reduce(|| (0.0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+    if count == 0 {
+        return 1.0;
+    }
+    return sum / count as f64;
+}
+
+/// 将帧号列表合并为区间, 便于打印
+fn frame_ranges(frames: &[i64]) -> String {
+    let mut result = Vec::<String>::new();
+    let mut iter = frames.iter().peekable();
+    while let Some(&begin) = iter.next() {
+        let mut end = begin;
+        while iter.peek().map_or(false, |&&next| next == end + 1) {
+            end = *iter.next().unwrap();
+        }
+        if begin == end {
+            result.push(format!("{}", begin));
+        } else {
+            result.push(format!("{}-{}", begin, end));
+        }
+    }
+    return result.join(", ");
+}
+
+/// 同时解码输入与输出视频, 逐帧比较字幕区域以外的画面与时间戳
+pub fn run(arg: &InputArg, verify_arg: &VerifyArg) -> anyhow::Result<()> {
+    let input_name = arg
+        .input
+        .first()
+        .ok_or(anyhow!("No input file specified"))?;
+    if arg.input.len() > 1 || arg.input_list.is_some() {
+        warn!("Only the first input file is verified");
+    }
+    if arg.start.is_some() || arg.end.is_some() || !arg.keep.is_empty() {
+        return Err(anyhow!("Verifying trimmed output is not supported"));
+    }
+    let mut input_reader = FrameReader::open(input_name, arg.video_stream, Pixel::RGB24, None)?;
+    let (width, height) = (input_reader.width(), input_reader.height());
+    let mut output_reader =
+        FrameReader::open(&arg.output, None, Pixel::RGB24, Some((width, height)))?;
+    if (output_reader.width(), output_reader.height()) != (width, height) {
+        warn!(
+            "Output video is {}x{}, input is {}x{}, comparing after scaling",
+            output_reader.width(),
+            output_reader.height(),
+            width,
+            height
+        );
+    }
+    let subtitles = load_subtitles(&PathBuf::from(&arg.subtitle_files))
+        .map_err(|e| anyhow!("Failed to read subtitles: {}\n", e))?;
+    info!("{} subtitles loaded.", subtitles.len());
+    let total_frames = subtitle_frames(&subtitles).max(input_reader.frames());
+    let mut render_data = Vec::<RenderData>::new();
+    init_render_data(
+        &mut render_data,
+        &subtitles,
+        total_frames,
+        arg.top_offset,
+        arg.bottom_offset,
+    )?;
+
+    let mut report = match &verify_arg.report {
+        Some(path) => {
+            let mut file = std::fs::File::create(path)
+                .map_err(|e| anyhow!("Failed to create report {}: {}", path, e))?;
+            writeln!(
+                file,
+                "frame,input_time,output_time,psnr,ssim,subtitle,status"
+            )?;
+            Some(file)
+        }
+        None => None,
+    };
+    let input_time_base = f64::from(input_reader.time_base());
+    let output_time_base = f64::from(output_reader.time_base());
+    let frame_rate = f64::from(input_reader.frame_rate());
+    // 时间戳偏差超过半帧视为不连续
+    let tolerance = if frame_rate > 0.0 {
+        0.5 / frame_rate
+    } else {
+        0.02
+    };
+
+    let mut input_origin = None;
+    let mut output_origin = None;
+    let mut last_output_time = f64::NEG_INFINITY;
+    let mut compared: i64 = 0;
+    let (mut input_count, mut output_count) = (0i64, 0i64);
+    let mut differing = Vec::<i64>::new();
+    let mut discontinuous = Vec::<i64>::new();
+    let mut min_psnr = f64::INFINITY;
+    let mut ssim_sum = 0.0;
+    loop {
+        let input_frame = input_reader.next_frame()?;
+        let output_frame = output_reader.next_frame()?;
+        input_count += input_frame.is_some() as i64;
+        output_count += output_frame.is_some() as i64;
+        let (input_frame, output_frame) = match (input_frame, output_frame) {
+            (Some(a), Some(b)) => (a, b),
+            (None, None) => break,
+            _ => continue,
+        };
+        compared += 1;
+        let frame_idx = compared;
+        let input_time = input_frame.pts().unwrap_or(0) as f64 * input_time_base;
+        let output_time = output_frame.pts().unwrap_or(0) as f64 * output_time_base;
+        let input_time = input_time - *input_origin.get_or_insert(input_time);
+        let output_time = output_time - *output_origin.get_or_insert(output_time);
+        let mut status = vec![];
+        if (output_time - input_time).abs() > tolerance || output_time <= last_output_time {
+            discontinuous.push(frame_idx);
+            status.push("timestamp");
+        }
+        last_output_time = output_time;
+
+        let regions = subtitle_regions(
+            render_data.get(frame_idx as usize - 1),
+            width as i32,
+            height as i32,
+            arg.top_offset,
+            arg.bottom_offset,
+        );
+        let psnr = masked_psnr(&input_frame, &output_frame, &regions);
+        let ssim = masked_ssim(&input_frame, &output_frame, &regions);
+        if psnr < verify_arg.psnr_threshold {
+            differing.push(frame_idx);
+            status.push("differs");
+        }
+        min_psnr = min_psnr.min(psnr);
+        ssim_sum += ssim;
+        if let Some(file) = &mut report {
+            writeln!(
+                file,
+                "{},{:.6},{:.6},{:.3},{:.5},{},{}",
+                frame_idx,
+                input_time,
+                output_time,
+                psnr,
+                ssim,
+                !regions.is_empty(),
+                if status.is_empty() {
+                    "ok".to_string()
+                } else {
+                    status.join("|")
+                }
+            )?;
+        }
+        if frame_idx % 1000 == 0 {
+            info!("Verified {} frames", frame_idx);
+        }
+    }
+
+    info!(
+        "Frame count: input {}, output {}{}",
+        input_count,
+        output_count,
+        if input_count == output_count {
+            ", match"
+        } else {
+            ", MISMATCH"
+        }
+    );
+    if compared > 0 {
+        info!(
+            "Outside subtitle regions: min PSNR {:.3} dB, mean SSIM {:.5}",
+            min_psnr,
+            ssim_sum / compared as f64
+        );
+    }
+    if !discontinuous.is_empty() {
+        warn!(
+            "{} frames with timestamp drift over {:.4} secs: {}",
+            discontinuous.len(),
+            tolerance,
+            frame_ranges(&discontinuous)
+        );
+    }
+    if !differing.is_empty() {
+        warn!(
+            "{} frames differ where no subtitle should be (PSNR < {} dB): {}",
+            differing.len(),
+            verify_arg.psnr_threshold,
+            frame_ranges(&differing)
+        );
+    }
+    if input_count != output_count || !discontinuous.is_empty() || !differing.is_empty() {
+        return Err(anyhow!("Verification failed"));
+    }
+    info!("Verification passed.");
+    return Ok(());
+}
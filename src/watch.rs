@@ -0,0 +1,219 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::anyhow;
+use log::{error, info, warn};
+use toml::{value::Table, Value};
+
+use crate::{
+    archive::is_archive,
+    cmdline::WatchArg,
+    jobs::{run_job, Job, JobStatus},
+};
+
+// 任务文件夹中的任务清单, 键与命令行长参数同名, 路径相对于任务文件夹
+const MANIFEST: &str = "job.toml";
+// 任务日志, 随任务文件夹一同移动
+const JOB_LOG: &str = "villagers.log";
+const VIDEO_EXTENSIONS: [&str; 8] = ["mp4", "mkv", "mov", "flv", "avi", "ts", "m2ts", "webm"];
+
+/// 最近的修改时间, 用于判断文件是否仍在复制中
+fn last_modified(path: &Path) -> std::io::Result<SystemTime> {
+    let mut latest = std::fs::metadata(path)?.modified()?;
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            latest = latest.max(last_modified(&entry?.path())?);
+        }
+    }
+    return Ok(latest);
+}
+
+fn is_video(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| {
+                VIDEO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+            })
+}
+
+/// 由任务文件夹生成参数表: 优先读取任务清单, 否则要求文件夹中恰好有一个视频与一个字幕文件夹或压缩包
+fn job_table(dir: &Path) -> anyhow::Result<Table> {
+    let manifest = dir.join(MANIFEST);
+    if manifest.exists() {
+        let content = std::fs::read_to_string(&manifest)
+            .map_err(|e| anyhow!("Failed to read {}: {}", manifest.display(), e))?;
+        return content
+            .parse::<Value>()
+            .map_err(|e| anyhow!("Invalid manifest {}: {}", manifest.display(), e))?
+            .as_table()
+            .cloned()
+            .ok_or(anyhow!("Invalid manifest {}", manifest.display()));
+    }
+    let mut videos = vec![];
+    let mut subtitle_dirs = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() || is_archive(&path) {
+            subtitle_dirs.push(path);
+        } else if is_video(&path) {
+            videos.push(path);
+        }
+    }
+    let (video, subtitle_dir) = match (videos.as_slice(), subtitle_dirs.as_slice()) {
+        ([video], [subtitle_dir]) => (video, subtitle_dir),
+        _ => {
+            return Err(anyhow!(
+                "Expected {} or exactly one video and one subtitle folder or archive, found {} videos and {} folders or archives",
+                MANIFEST,
+                videos.len(),
+                subtitle_dirs.len()
+            ))
+        }
+    };
+    let file_name = |path: &Path| path.file_name().unwrap().to_string_lossy().to_string();
+    let stem = video.file_stem().unwrap().to_string_lossy();
+    let mut table = Table::new();
+    table.insert("input".to_string(), Value::String(file_name(video)));
+    table.insert(
+        "subtitle-files".to_string(),
+        Value::String(file_name(subtitle_dir)),
+    );
+    table.insert(
+        "output".to_string(),
+        Value::String(format!("{}-embedded.mp4", stem)),
+    );
+    return Ok(table);
+}
+
+fn is_cross_device(e: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    return e.raw_os_error() == Some(libc::EXDEV);
+    // ERROR_NOT_SAME_DEVICE
+    #[cfg(not(unix))]
+    return e.raw_os_error() == Some(17);
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let path = entry?.path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &target)?;
+        } else {
+            std::fs::copy(&path, &target)?;
+        }
+    }
+    return Ok(());
+}
+
+/// 移动任务文件夹; 收件箱常在网络共享等其他挂载点上, 跨设备时改为复制后删除
+fn move_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    match std::fs::rename(from, to) {
+        Ok(()) => return Ok(()),
+        Err(e) if is_cross_device(&e) => {}
+        Err(e) => return Err(anyhow!("Failed to move {}: {}", from.display(), e)),
+    }
+    if let Err(e) = copy_dir(from, to) {
+        let _ = std::fs::remove_dir_all(to);
+        return Err(anyhow!(
+            "Failed to copy {} to {}: {}",
+            from.display(),
+            to.display(),
+            e
+        ));
+    }
+    std::fs::remove_dir_all(from)
+        .map_err(|e| anyhow!("Failed to remove {}: {}", from.display(), e))?;
+    return Ok(());
+}
+
+/// 目标位置已存在同名文件夹时追加序号
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.join(name);
+    let mut i = 1;
+    while path.exists() {
+        path = dir.join(format!("{}.{}", name, i));
+        i += 1;
+    }
+    return path;
+}
+
+fn process(arg: &WatchArg, folder: &Path) -> anyhow::Result<()> {
+    let name = folder.file_name().unwrap().to_string_lossy().to_string();
+    // 先移出收件箱, 避免重复处理
+    let work = unique_path(Path::new(&arg.work_dir), &name);
+    move_dir(folder, &work)?;
+    info!("Job {} started", name);
+    let (status, message) =
+        match job_table(&work).and_then(|table| Job::from_table(name.clone(), &table, &work)) {
+            Ok(job) => {
+                let result = run_job(&job, &work.join(JOB_LOG));
+                (result.status, result.error)
+            }
+            Err(e) => {
+                // 无法识别的任务文件夹也写入日志, 便于提交者排查
+                let message = e.to_string();
+                std::fs::write(work.join(JOB_LOG), format!("{}\n", message))?;
+                (JobStatus::Failed, Some(message))
+            }
+        };
+    let target_dir = match status {
+        JobStatus::Ok => &arg.done_dir,
+        JobStatus::Failed => &arg.failed_dir,
+    };
+    let target = unique_path(Path::new(target_dir), &name);
+    move_dir(&work, &target)?;
+    match status {
+        JobStatus::Ok => info!("Job {} finished, moved to {}", name, target.display()),
+        JobStatus::Failed => error!(
+            "Job {} failed: {}, moved to {}",
+            name,
+            message.as_deref().unwrap_or("unknown error"),
+            target.display()
+        ),
+    }
+    return Ok(());
+}
+
+/// 监视收件箱文件夹, 依次处理放入的任务文件夹, 完成后移入done或failed文件夹
+pub fn run(arg: &WatchArg) -> anyhow::Result<()> {
+    for dir in [&arg.inbox, &arg.work_dir, &arg.done_dir, &arg.failed_dir] {
+        std::fs::create_dir_all(dir).map_err(|e| anyhow!("Failed to create {}: {}", dir, e))?;
+    }
+    for entry in std::fs::read_dir(&arg.work_dir)? {
+        warn!(
+            "Unfinished job found: {}, move it back to {} to retry",
+            entry?.path().display(),
+            arg.inbox
+        );
+    }
+    info!("Watching {} for new jobs", arg.inbox);
+    let settle = Duration::from_secs(arg.settle);
+    loop {
+        let mut folders = std::fs::read_dir(&arg.inbox)
+            .map_err(|e| anyhow!("Failed to read {}: {}", arg.inbox, e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect::<Vec<PathBuf>>();
+        folders.sort();
+        for folder in folders.iter() {
+            // 最近仍有修改的文件夹可能还在复制中, 下一轮再处理
+            let idle = last_modified(folder)
+                .ok()
+                .and_then(|time| time.elapsed().ok())
+                .map_or(false, |idle| idle >= settle);
+            if !idle {
+                continue;
+            }
+            if let Err(e) = process(arg, folder) {
+                error!("Failed to process {}: {}", folder.display(), e);
+            }
+        }
+        std::thread::sleep(Duration::from_secs(arg.interval));
+    }
+}
@@ -0,0 +1,60 @@
+//! 重新编码的音频时长与输入一致: 滤镜输出的时间基须与编码器相同, 与容器的时间基无关
+
+mod common;
+
+use common::{audio_duration, has_encoders, temp_dir, write_clip, FPS};
+use villagers_embedding_tool::EmbeddingBuilder;
+
+const FRAMES: i64 = 50;
+
+fn transcode_audio(name: &str, container: &str) {
+    if !has_encoders(&["libx264", "aac"]) {
+        return;
+    }
+    let dir = temp_dir(name);
+    let input = dir.join(format!("input.{}", container));
+    let output = dir.join("output.mp4");
+    let subtitle_dir = dir.join("subtitles");
+    std::fs::create_dir_all(&subtitle_dir).unwrap();
+    write_clip(&input, FRAMES, true);
+    let expected = FRAMES as f64 / FPS as f64;
+    let input_duration = audio_duration(&input);
+    assert!(
+        (input_duration - expected).abs() < 0.1,
+        "Input audio lasts {} secs, expected {}",
+        input_duration,
+        expected
+    );
+
+    EmbeddingBuilder::from_args([
+        format!("--input={}", input.display()),
+        format!("--output={}", output.display()),
+        format!("--subtitle-files={}", subtitle_dir.display()),
+        "--audio-codec=aac".to_string(),
+    ])
+    .unwrap()
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+    let output_duration = audio_duration(&output);
+    assert!(
+        (output_duration - expected).abs() < 0.1,
+        "Output audio lasts {} secs, expected {}",
+        output_duration,
+        expected
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn audio_duration_mkv() {
+    // MKV的时间基为1/1000
+    transcode_audio("audio-mkv", "mkv");
+}
+
+#[test]
+fn audio_duration_ts() {
+    // MPEG-TS的时间基为1/90000
+    transcode_audio("audio-ts", "ts");
+}
@@ -0,0 +1,204 @@
+//! 集成测试共用的工具: 生成测试视频, 统计帧数与时长
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use ffmpeg_next::{
+    codec, encoder,
+    format::{self, Pixel, Sample},
+    frame::{Audio, Video},
+    media::Type,
+    ChannelLayout, Dictionary, Error, Packet, Rational,
+};
+use ffmpeg_sys_next::avcodec_parameters_to_context;
+
+pub const WIDTH: u32 = 64;
+pub const HEIGHT: u32 = 48;
+pub const FPS: i32 = 25;
+pub const SAMPLE_RATE: i32 = 48000;
+
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("villagers-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    return dir;
+}
+
+/// 运行时的ffmpeg缺少测试所需的编码器时返回false, 由调用方跳过测试
+pub fn has_encoders(names: &[&str]) -> bool {
+    ffmpeg_next::init().unwrap();
+    for name in names {
+        if encoder::find_by_name(name).is_none() {
+            eprintln!("Skipping: ffmpeg is built without the {} encoder", name);
+            return false;
+        }
+    }
+    return true;
+}
+
+/// 生成带B帧的H.264测试视频, 帧率25; audio为true时附带48kHz的AAC音频, 时长与视频相同
+///
+/// 容器格式由文件扩展名决定, 如mp4, mkv, ts
+pub fn write_clip(path: &Path, frames: i64, audio: bool) {
+    ffmpeg_next::init().unwrap();
+    let mut octx = format::output(&path).unwrap();
+    let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+
+    let h264 = encoder::find(codec::Id::H264).unwrap();
+    let video_time_base = Rational::new(1, FPS);
+    let mut video_encoder = codec::context::Context::new().encoder().video().unwrap();
+    video_encoder.set_width(WIDTH);
+    video_encoder.set_height(HEIGHT);
+    video_encoder.set_format(Pixel::YUV420P);
+    video_encoder.set_time_base(video_time_base);
+    video_encoder.set_frame_rate(Some(Rational::new(FPS, 1)));
+    video_encoder.set_max_b_frames(3);
+    if global_header {
+        video_encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+    }
+    let mut video_encoder = video_encoder
+        .open_as_with(h264, {
+            let mut dict = Dictionary::new();
+            dict.set("preset", "medium");
+            dict
+        })
+        .unwrap();
+    let video_index = {
+        let mut stream = octx.add_stream(h264).unwrap();
+        stream.set_parameters(&video_encoder);
+        stream.set_time_base(video_time_base);
+        stream.index()
+    };
+
+    let audio_time_base = Rational::new(1, SAMPLE_RATE);
+    let mut audio_encoder = if audio {
+        let aac = encoder::find_by_name("aac").unwrap();
+        let mut audio_encoder = codec::context::Context::new().encoder().audio().unwrap();
+        audio_encoder.set_rate(SAMPLE_RATE);
+        audio_encoder.set_channel_layout(ChannelLayout::MONO);
+        audio_encoder.set_channels(1);
+        audio_encoder.set_format(Sample::F32(format::sample::Type::Planar));
+        audio_encoder.set_bit_rate(64000);
+        audio_encoder.set_time_base(audio_time_base);
+        if global_header {
+            audio_encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let audio_encoder = audio_encoder.open_as(aac).unwrap();
+        let mut stream = octx.add_stream(aac).unwrap();
+        stream.set_parameters(&audio_encoder);
+        stream.set_time_base(audio_time_base);
+        Some((audio_encoder, stream.index()))
+    } else {
+        None
+    };
+    octx.write_header().unwrap();
+
+    let write_packets = |encoder: &mut encoder::Encoder,
+                         octx: &mut format::context::Output,
+                         index: usize,
+                         time_base: Rational| {
+        let stream_time_base = octx.stream(index).unwrap().time_base();
+        let mut packet = Packet::empty();
+        while encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(index);
+            packet.rescale_ts(time_base, stream_time_base);
+            packet.write_interleaved(octx).unwrap();
+        }
+    };
+    let total_samples = frames * SAMPLE_RATE as i64 / FPS as i64;
+    let mut samples_done = 0;
+    for i in 0..frames {
+        let mut frame = Video::new(Pixel::YUV420P, WIDTH, HEIGHT);
+        for plane in 0..3 {
+            let value = ((i * 7 + plane as i64 * 40) % 256) as u8;
+            frame.data_mut(plane).fill(value);
+        }
+        frame.set_pts(Some(i));
+        video_encoder.send_frame(&frame).unwrap();
+        write_packets(&mut video_encoder, &mut octx, video_index, video_time_base);
+
+        // 音频与视频交错写入
+        if let Some((audio_encoder, audio_index)) = audio_encoder.as_mut() {
+            let frame_size = (audio_encoder.frame_size() as i64).max(1024);
+            let until = ((i + 1) * SAMPLE_RATE as i64 / FPS as i64).min(total_samples);
+            while samples_done < until {
+                let samples = frame_size.min(total_samples - samples_done);
+                let mut frame = Audio::new(
+                    Sample::F32(format::sample::Type::Planar),
+                    samples as usize,
+                    ChannelLayout::MONO,
+                );
+                frame.set_rate(SAMPLE_RATE as u32);
+                for (n, sample) in frame.plane_mut::<f32>(0).iter_mut().enumerate() {
+                    let t = (samples_done + n as i64) as f32 / SAMPLE_RATE as f32;
+                    *sample = (t * 440.0 * std::f32::consts::TAU).sin() * 0.25;
+                }
+                frame.set_pts(Some(samples_done));
+                audio_encoder.send_frame(&frame).unwrap();
+                write_packets(audio_encoder, &mut octx, *audio_index, audio_time_base);
+                samples_done += samples;
+            }
+        }
+    }
+    video_encoder.send_eof().unwrap();
+    write_packets(&mut video_encoder, &mut octx, video_index, video_time_base);
+    if let Some((audio_encoder, audio_index)) = audio_encoder.as_mut() {
+        audio_encoder.send_eof().unwrap();
+        write_packets(audio_encoder, &mut octx, *audio_index, audio_time_base);
+    }
+    octx.write_trailer().unwrap();
+}
+
+/// 解码视频, 返回视频帧数
+pub fn count_frames(path: &Path) -> i64 {
+    let mut ictx = format::input(&path).unwrap();
+    let stream = ictx.streams().best(Type::Video).unwrap();
+    let stream_index = stream.index();
+    let mut context = codec::context::Context::new();
+    unsafe {
+        let code =
+            avcodec_parameters_to_context(context.as_mut_ptr(), stream.parameters().as_ptr());
+        assert!(
+            code >= 0,
+            "Failed to copy parameters: {}",
+            Error::from(code)
+        );
+    }
+    let mut decoder = context.decoder().video().unwrap();
+    let mut count = 0;
+    let mut frame = Video::empty();
+    for (stream, packet) in ictx.packets() {
+        if stream.index() == stream_index {
+            decoder.send_packet(&packet).unwrap();
+            while decoder.receive_frame(&mut frame).is_ok() {
+                count += 1;
+            }
+        }
+    }
+    decoder.send_eof().unwrap();
+    while decoder.receive_frame(&mut frame).is_ok() {
+        count += 1;
+    }
+    return count;
+}
+
+/// 按数据包的时间戳计算音频流的时长 (秒)
+pub fn audio_duration(path: &Path) -> f64 {
+    let mut ictx = format::input(&path).unwrap();
+    let stream = ictx.streams().best(Type::Audio).unwrap();
+    let stream_index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let mut start = i64::MAX;
+    let mut end = i64::MIN;
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        if let Some(pts) = packet.pts() {
+            start = start.min(pts);
+            end = end.max(pts + packet.duration());
+        }
+    }
+    assert!(start <= end, "No audio packet in {}", path.display());
+    return (end - start) as f64 * time_base;
+}
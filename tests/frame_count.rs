@@ -0,0 +1,57 @@
+//! 压制前后帧数一致: 编码器延迟输出的帧在结尾须全部取出
+
+mod common;
+
+use common::{count_frames, has_encoders, temp_dir, write_clip};
+use villagers_embedding_tool::EmbeddingBuilder;
+
+fn embed_and_count(name: &str, frames: i64, args: &[&str]) {
+    if !has_encoders(&["libx264"]) {
+        return;
+    }
+    let dir = temp_dir(name);
+    let input = dir.join("input.mp4");
+    let output = dir.join("output.mp4");
+    let subtitle_dir = dir.join("subtitles");
+    std::fs::create_dir_all(&subtitle_dir).unwrap();
+    write_clip(&input, frames, false);
+    assert_eq!(count_frames(&input), frames);
+
+    EmbeddingBuilder::from_args(args.iter().map(|arg| arg.to_string()).chain([
+        format!("--input={}", input.display()),
+        format!("--output={}", output.display()),
+        format!("--subtitle-files={}", subtitle_dir.display()),
+    ]))
+    .unwrap()
+    .build()
+    .unwrap()
+    .run()
+    .unwrap();
+    assert_eq!(count_frames(&output), frames);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn frame_count_single_chunk() {
+    embed_and_count("single-chunk", 50, &["--chunk-size=1000"]);
+}
+
+#[test]
+fn frame_count_partial_last_chunk() {
+    // 最后一块不满; 默认的zerolatency下编码器没有延迟, 只检查解码器的冲刷
+    embed_and_count("partial-chunk", 53, &["--chunk-size=20"]);
+}
+
+#[test]
+fn frame_count_encoder_delay() {
+    // 不使用zerolatency时, slower预设的lookahead使编码器缓存数十帧, 结尾须冲刷编码器
+    embed_and_count(
+        "encoder-delay",
+        97,
+        &[
+            "--chunk-size=16",
+            "--x264-preset=slower",
+            "--x264-tune=none",
+        ],
+    );
+}